@@ -0,0 +1,112 @@
+use std::{collections::VecDeque, ops::Deref};
+use js_sys::{Object, Uint8Array};
+use wasm_bindgen::JsValue;
+use web_sys::{EncodedVideoChunkType, EncodedVideoChunkInit, EncodedVideoChunk};
+
+use crate::{video::{frames::{FrameCache, FrameCacheError, FrameInfo}, rate::FrameRate}, config::set_fields};
+
+impl From<JsValue> for FrameCacheError {
+    fn from(value: JsValue) -> Self {
+        match value.as_string() {
+            Some(err) => FrameCacheError::Init(err),
+            None => FrameCacheError::Init(format!("{value:?}"))
+        }
+    }
+}
+
+impl FrameCache {
+    /// `bytes` are the bytes of this frame
+    pub fn to_chunk(&self, bytes: &[u8]) -> Result<EncodedVideoChunk, FrameCacheError> {
+        let chunk_type = match self.keyframe {
+            true => EncodedVideoChunkType::Key,
+            false => EncodedVideoChunkType::Delta,
+        };
+
+        let data = Uint8Array::new_with_length(self.size);
+
+        data.copy_from(bytes);
+        let obj = data.deref();
+        let mut init = EncodedVideoChunkInit::new(obj, self.timestamp as f64, chunk_type);
+        if let Some(duration) = self.duration {
+            init.duration(duration as f64);
+        }
+        let chunk = EncodedVideoChunk::new(&init)?;
+
+        Ok(chunk)
+    }
+}
+
+impl FrameInfo {
+    /// A plain object for the frame table, times are in microseconds
+    pub fn to_object(&self) -> Result<Object, JsValue> {
+        let object = Object::new();
+        set_fields(&object, &[
+            ("index", self.index.into()),
+            ("decodeIndex", self.decode_index.into()),
+            ("decodeTimestamp", (self.decode_timestamp as f64).into()),
+            ("timestamp", (self.timestamp as f64).into()),
+            ("duration", self.duration.map(|duration| duration as f64).into()),
+            ("size", self.size.into()),
+            ("keyframe", self.keyframe.into()),
+            ("offset", self.offset.into()),
+        ])?;
+
+        Ok(object)
+    }
+}
+
+impl FrameRate {
+    /// A plain object for the frame rate, intervals are in microseconds
+    pub fn to_object(&self) -> Result<Object, JsValue> {
+        let object = Object::new();
+        set_fields(&object, &[
+            ("rate", self.rate.into()),
+            ("declared", self.declared.into()),
+            ("variable", self.variable.into()),
+            ("minInterval", (self.min_interval as f64).into()),
+            ("maxInterval", (self.max_interval as f64).into()),
+            ("meanInterval", self.mean_interval.into()),
+        ])?;
+
+        Ok(object)
+    }
+}
+
+pub const DEFAULT_CHUNK_CACHE_SIZE: usize = 8;
+
+/// Keeps the most recently created chunks around, so decoding the same frames again (e.g. when
+/// seeking back into the current group of pictures) doesn't copy them into JS memory again.
+pub struct ChunkCache {
+    capacity: usize,
+    chunks: VecDeque<(usize, EncodedVideoChunk)>,
+}
+
+impl ChunkCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, chunks: VecDeque::with_capacity(capacity) }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.chunks.truncate(capacity);
+    }
+
+    pub fn get(&mut self, idx: usize, frame: &FrameCache, bytes: Option<&[u8]>) -> Result<EncodedVideoChunk, FrameCacheError> {
+        if let Some(position) = self.chunks.iter().position(|(cached, _)| *cached == idx) {
+            let entry = self.chunks.remove(position).unwrap();
+            let chunk = entry.1.clone();
+            self.chunks.push_front(entry);
+            return Ok(chunk);
+        }
+
+        let bytes = bytes.ok_or(FrameCacheError::Init(format!("The data of frame {idx} has not been loaded")))?;
+        let chunk = frame.to_chunk(bytes)?;
+
+        if self.capacity > 0 {
+            self.chunks.truncate(self.capacity - 1);
+            self.chunks.push_front((idx, chunk.clone()));
+        }
+
+        Ok(chunk)
+    }
+}
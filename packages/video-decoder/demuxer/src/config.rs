@@ -0,0 +1,121 @@
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use wasm_bindgen::{JsError, JsValue};
+use web_sys::{HardwareAcceleration, VideoColorSpaceInit, VideoDecoderConfig};
+
+use crate::video::{color::{ColorSpace, ColorInfo, MasteringDisplay, ContentLightLevel}, config::DecoderConfig, display::Orientation};
+
+impl ColorSpace {
+    /// The web-sys enums lack most of the HDR values, so the names are set on the object directly.
+    pub fn to_init(&self) -> Result<VideoColorSpaceInit, JsValue> {
+        let init = VideoColorSpaceInit::new();
+        let fields = [
+            ("primaries", self.primaries_name()),
+            ("transfer", self.transfer_name()),
+            ("matrix", self.matrix_name()),
+        ];
+
+        for (field, name) in fields {
+            if let Some(name) = name {
+                Reflect::set(&init, &field.into(), &name.into())?;
+            }
+        }
+
+        if let Some(full_range) = self.full_range {
+            Reflect::set(&init, &"fullRange".into(), &full_range.into())?;
+        }
+
+        Ok(init)
+    }
+}
+
+pub(crate) fn set_fields(object: &Object, fields: &[(&str, JsValue)]) -> Result<(), JsValue> {
+    for (field, value) in fields {
+        Reflect::set(object, &(*field).into(), value)?;
+    }
+
+    Ok(())
+}
+
+fn chromaticity((x, y): (f64, f64)) -> JsValue {
+    Array::of2(&x.into(), &y.into()).into()
+}
+
+impl MasteringDisplay {
+    pub fn to_object(&self) -> Result<Object, JsValue> {
+        let object = Object::new();
+        set_fields(&object, &[
+            ("red", chromaticity(self.red)),
+            ("green", chromaticity(self.green)),
+            ("blue", chromaticity(self.blue)),
+            ("whitePoint", chromaticity(self.white_point)),
+            ("maxLuminance", self.max_luminance.into()),
+            ("minLuminance", self.min_luminance.into()),
+        ])?;
+
+        Ok(object)
+    }
+}
+
+impl ContentLightLevel {
+    pub fn to_object(&self) -> Result<Object, JsValue> {
+        let object = Object::new();
+        set_fields(&object, &[
+            ("maxCLL", self.max_content_light_level.into()),
+            ("maxFALL", self.max_frame_average_light_level.into()),
+        ])?;
+
+        Ok(object)
+    }
+}
+
+impl ColorInfo {
+    /// The colour space as a `VideoColorSpaceInit`, with the HDR metadata next to it
+    pub fn to_object(&self) -> Result<Object, JsValue> {
+        let object = Object::new();
+        Reflect::set(&object, &"colorSpace".into(), &self.color_space.to_init()?.into())?;
+
+        if let Some(mastering_display) = &self.mastering_display {
+            Reflect::set(&object, &"masteringDisplay".into(), &mastering_display.to_object()?.into())?;
+        }
+        if let Some(content_light_level) = &self.content_light_level {
+            Reflect::set(&object, &"contentLightLevel".into(), &content_light_level.to_object()?.into())?;
+        }
+
+        Ok(object)
+    }
+}
+
+impl DecoderConfig {
+    pub fn to_web_config(
+        &self,
+        fallback_codec: Option<String>,
+        hardware_acceleration: HardwareAcceleration,
+        optimize_for_latency: bool,
+    ) -> Result<VideoDecoderConfig, JsValue> {
+        let codec = self.codec.clone()
+            .or(fallback_codec)
+            .ok_or_else(|| JsError::new("Could not determine the codec of the video track"))?;
+
+        let mut config = VideoDecoderConfig::new(&codec);
+        config
+            .coded_width(self.coded_width)
+            .coded_height(self.coded_height)
+            .display_aspect_width(self.display_width)
+            .display_aspect_height(self.display_height)
+            .color_space(&self.color_info.color_space.to_init()?)
+            .hardware_acceleration(hardware_acceleration)
+            .optimize_for_latency(optimize_for_latency);
+
+        if let Some(description) = &self.description {
+            config.description(&Uint8Array::from(description.as_slice()));
+        }
+
+        // Not in web-sys yet, decoders that support it output upright frames
+        if self.orientation != Orientation::default() {
+            Reflect::set(&config, &"rotation".into(), &self.orientation.rotation.into())?;
+            Reflect::set(&config, &"flip".into(), &self.orientation.flip.into())?;
+        }
+
+        Ok(config)
+    }
+}
@@ -1,177 +1,550 @@
-use std::cmp::Ordering;
-
-use video::frames::FrameCacheStore;
+#[cfg(feature = "web")]
+use std::{cell::RefCell, rc::Rc};
+
+#[cfg(feature = "web")]
+use video::{stream::VideoStream, range::RangeFile, format::Format, plan::{self, DecoderState}, rate::FrameRate};
+#[cfg(feature = "web")]
+use source::{Source, RangeReader};
+#[cfg(feature = "web")]
+use chunk::{ChunkCache, DEFAULT_CHUNK_CACHE_SIZE};
+#[cfg(feature = "web")]
 use js_sys::ArrayBuffer;
-use web_sys::VideoDecoder;
-
-mod log;
-mod video;
-
+#[cfg(feature = "web")]
+use video::config::DecoderConfig;
+#[cfg(feature = "web")]
+use web_sys::{VideoDecoder, VideoDecoderConfig, HardwareAcceleration, EncodedVideoChunk};
+
+#[doc(hidden)]
+pub mod log;
+pub mod video;
+#[cfg(feature = "web")]
+mod chunk;
+#[cfg(feature = "web")]
+mod config;
+#[cfg(feature = "web")]
+mod source;
+
+#[cfg(feature = "web")]
 use wasm_bindgen::prelude::*;
 
+#[cfg(feature = "web")]
 #[wasm_bindgen]
 pub struct Demuxer {
-    first_render: bool,
-    keyframes: FrameCacheStore,
-    current_frame: usize,
-    coded_width: u32,
-    coded_height: u32,
+    source: Source,
+    chunks: ChunkCache,
+    // What the decoder driven by `decode` & `seek` has been fed
+    decoder_state: DecoderState,
     duration: f64,
-    codec: Option<String>,
+    frame_rate: Option<f64>,
+    config: Option<DecoderConfig>,
 }
 
+#[cfg(feature = "web")]
 #[wasm_bindgen]
 impl Demuxer {
+    /// Creates a demuxer that is fed the file with `append` while it loads, the format is detected
+    /// from the first bytes when it isn't given.
+    #[wasm_bindgen(constructor)]
+    pub fn new(format: Option<ContainerFormat>) -> Result<Demuxer, JsValue> {
+        let format = match format {
+            Some(ContainerFormat::Mkv) => Some(Format::Matroska),
+            Some(ContainerFormat::Mp4) => Some(Format::IsoBmff),
+            Some(format) => return Err(JsError::new(&format!("Unsupported container format: {format:?}")).into()),
+            None => None,
+        };
+
+        Ok(Demuxer::from_source(Source::Stream(VideoStream::new(format))))
+    }
+
+    /// Adds the next part of the file, frames become decodable as soon as they are complete
+    pub fn append(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        self.stream()?.append(bytes)?;
+        self.read_headers()
+    }
+
+    /// Marks the file as complete, fails when it ended before any frame could be read
+    #[wasm_bindgen(js_name = endOfStream)]
+    pub fn end_of_stream(&mut self) -> Result<(), JsValue> {
+        self.stream()?.end_of_stream()?;
+        self.read_headers()
+    }
+
+    /// Fetches the frames `from` up to and including `to` when the file is read by range, which has
+    /// to be done before decoding them. Matroska frames are only counted once they are fetched.
+    pub fn fetch(&self, from: usize, to: usize) -> js_sys::Promise {
+        self.source.fetch(from, to)
+    }
+
+    /// Whether the headers have been loaded, the decoder can be configured from then on
+    pub fn ready(&self) -> bool {
+        self.config.is_some()
+    }
+
     #[wasm_bindgen(js_name = codedWidth)]
     pub fn coded_width(&self) -> u32 {
-        self.coded_width
+        self.config.as_ref().map(|config| config.coded_width).unwrap_or(0)
     }
 
     #[wasm_bindgen(js_name = codedHeight)]
     pub fn coded_height(&self) -> u32 {
-        self.coded_height
+        self.config.as_ref().map(|config| config.coded_height).unwrap_or(0)
+    }
+
+    /// The size to display the frames at before they are rotated
+    #[wasm_bindgen(js_name = displayWidth)]
+    pub fn display_width(&self) -> Option<u32> {
+        self.config.as_ref().map(|config| config.display_width)
+    }
+
+    #[wasm_bindgen(js_name = displayHeight)]
+    pub fn display_height(&self) -> Option<u32> {
+        self.config.as_ref().map(|config| config.display_height)
+    }
+
+    /// The width & height of a pixel, undefined for square pixels
+    #[wasm_bindgen(js_name = pixelAspectRatio)]
+    pub fn pixel_aspect_ratio(&self) -> Option<Vec<u32>> {
+        self.config.as_ref()
+            .and_then(|config| config.pixel_aspect_ratio)
+            .map(|(horizontal, vertical)| vec![horizontal, vertical])
+    }
+
+    /// The clockwise rotation in degrees to display the frames upright, after flipping them
+    pub fn rotation(&self) -> u16 {
+        self.config.as_ref().map(|config| config.orientation.rotation).unwrap_or(0)
+    }
+
+    /// Whether the frames have to be mirrored horizontally before rotating them
+    pub fn flip(&self) -> bool {
+        self.config.as_ref().map(|config| config.orientation.flip).unwrap_or(false)
     }
 
     pub fn duration(&self) -> f64 {
         self.duration
     }
 
+    /// The number of frames that have been loaded, without the decode-only frames
     #[wasm_bindgen(js_name = frameCount)]
     pub fn frame_count(&self) -> usize {
-        self.keyframes.count()
+        self.source.with_frames(|frames| frames.presentation_count())
     }
 
+    /// Finds the frame with the given timestamp in microseconds, like the timestamp of a `VideoFrame`
     #[wasm_bindgen(js_name = timestampToFrame)]
-    pub fn timestamp_to_frame(&self, timestamp: u32) -> Option<usize> {
-        self.keyframes.timestamp_to_frame(timestamp as u64)
+    pub fn timestamp_to_frame(&self, timestamp: f64) -> Option<usize> {
+        self.source.with_frames(|frames| frames.timestamp_to_frame(timestamp as i64))
+    }
+
+    /// The loaded frames in presentation order: their index, decode index, decode timestamp,
+    /// timestamp, duration, byte size, keyframe flag and byte offset
+    pub fn frames(&self) -> Result<js_sys::Array, JsValue> {
+        self.source.with_frames(|frames| frames.table().iter().map(|frame| frame.to_object()).collect())
+    }
+
+    /// The duration of a frame in microseconds, unknown for the last frame when the container
+    /// doesn't store it
+    #[wasm_bindgen(js_name = frameDuration)]
+    pub fn frame_duration(&self, idx: usize) -> Option<f64> {
+        self.source
+            .with_frames(|frames| frames.presentation_to_decode(idx).and_then(|idx| frames.duration(idx)))
+            .map(|duration| duration as f64)
+    }
+
+    /// The frame rate of the loaded frames, checked against the rate the container declares.
+    /// Intervals are in microseconds, undefined before two frames are loaded
+    #[wasm_bindgen(js_name = frameRate)]
+    pub fn frame_rate(&self) -> Result<JsValue, JsValue> {
+        match self.source.with_frames(|frames| FrameRate::measure(frames, self.frame_rate)) {
+            Some(rate) => Ok(rate.to_object()?.into()),
+            None => Ok(JsValue::UNDEFINED),
+        }
     }
 
     pub fn codec(&self) -> Option<String> {
-        self.codec.clone()
+        self.config.as_ref().and_then(|config| config.codec.clone())
     }
 
-    pub fn decode(&mut self, from: usize, to: usize, decoder: &VideoDecoder) -> usize {
-        let skip_until = self.skip_to_keyframe(from);
-        let mut decoded: usize = 0;
+    /// The colour space to configure the decoder with and the HDR metadata to display the frames
+    #[wasm_bindgen(js_name = colorInfo)]
+    pub fn color_info(&self) -> Result<JsValue, JsValue> {
+        match &self.config {
+            Some(config) => Ok(config.color_info.to_object()?.into()),
+            None => Ok(JsValue::UNDEFINED),
+        }
+    }
 
-        for idx in (0..self.keyframes.count()).into_iter() {
-            if idx < skip_until {
-                continue;
-            }
+    /// The codec specific configuration bytes (e.g. avcC) to configure the decoder with
+    pub fn description(&self) -> Option<Vec<u8>> {
+        self.config.as_ref().and_then(|config| config.description.clone())
+    }
 
-            if idx > to {
-                break;
-            }
+    /// A complete decoder configuration, `codec` is used when the codec can't be determined from
+    /// the file itself.
+    #[wasm_bindgen(js_name = decoderConfig)]
+    pub fn decoder_config(
+        &self,
+        codec: Option<String>,
+        hardware_acceleration: Option<HardwareAcceleration>,
+        optimize_for_latency: Option<bool>,
+    ) -> Result<VideoDecoderConfig, JsValue> {
+        let config = self.config.as_ref().ok_or_else(|| JsError::new("The headers have not been loaded yet"))?;
+
+        config.to_web_config(
+            codec,
+            hardware_acceleration.unwrap_or(HardwareAcceleration::NoPreference),
+            optimize_for_latency.unwrap_or(false),
+        )
+    }
 
-            if self.current_frame == idx && !self.first_render {
-                continue;
-            }
+    /// Sets how many of the most recently created chunks are kept, `0` disables the cache
+    #[wasm_bindgen(js_name = setChunkCacheSize)]
+    pub fn set_chunk_cache_size(&mut self, size: usize) {
+        self.chunks.set_capacity(size);
+    }
+
+    /// Decodes the frames presented as `from` up to and including `to`, starting from the keyframe
+    /// before them unless the decoder already got there
+    pub fn decode(&mut self, from: usize, to: usize, decoder: &VideoDecoder) -> usize {
+        let state = self.decoder_state;
+        let planned = self.source.with_frames(|frames| plan::plan(frames, from, to, state));
 
-            self.current_frame = idx;
+        for idx in planned.iter() {
             console_log!("decode frame: {idx}");
-            self.render(decoder);
-            self.first_render = false;
-            decoded += 1;
+            self.feed(*idx, decoder);
         }
 
-        decoded
+        planned.len()
     }
 
-    pub fn seek(&mut self, frame: usize, decoder: &VideoDecoder) -> u32 {
-        let skip_until = self.skip_to_keyframe(frame);
+    /// Decodes up to the frame presented as `frame`, returns its timestamp
+    pub fn seek(&mut self, frame: usize, decoder: &VideoDecoder) -> f64 {
+        let state = self.decoder_state;
+        let planned = self.source.with_frames(|frames| plan::plan(frames, frame, frame, state));
 
-        for idx in (0..self.keyframes.count()).into_iter() {
-            if idx < skip_until {
-                continue;
-            }
+        for idx in planned {
+            self.feed(idx, decoder);
+        }
 
-            if idx > frame {
-                break;
-            }
+        self.source
+            .with_frames(|frames| frames.get(frames.presentation_to_decode(frame)?).map(|frame| frame.timestamp))
+            .map_or_else(|| self.current_timestamp(), |timestamp| timestamp as f64)
+    }
 
-            if self.current_frame == idx && !self.first_render {
-                continue;
-            }
+    /// The frames (in decode order) to feed a decoder so it outputs the frames presented as `from`
+    /// up to and including `to`, without decoding anything. `lastDecoded` is the last frame the
+    /// decoder got since it was configured, reset or flushed, if any. The chunks are created with
+    /// `chunk`.
+    #[wasm_bindgen(js_name = planDecode)]
+    pub fn plan_decode(&self, from: usize, to: usize, last_decoded: Option<usize>) -> Vec<usize> {
+        let state = last_decoded.map_or(DecoderState::Reset, DecoderState::Decoded);
+
+        self.source.with_frames(|frames| plan::plan(frames, from, to, state))
+    }
+
+    /// The chunk of a frame, `idx` is in decode order like the frames of `planDecode`
+    pub fn chunk(&mut self, idx: usize) -> Result<EncodedVideoChunk, JsValue> {
+        let chunks = &mut self.chunks;
+
+        self.source.with_frame(idx, |frame| match frame {
+            Some((frame, bytes)) => Ok(chunks.get(idx, frame, bytes).map_err(video::DemuxError::from)?),
+            None => Err(JsError::new(&format!("Frame {idx} has not been loaded")).into()),
+        })
+    }
+
+    /// Forgets what the decoder of `decode` & `seek` has been fed, which has to be done whenever it
+    /// is reset, flushed or has failed, so the next frame decoded is a keyframe
+    #[wasm_bindgen(js_name = resetDecoderState)]
+    pub fn reset_decoder_state(&mut self) {
+        self.decoder_state = DecoderState::Reset;
+    }
+
+    /// Decodes up to the frame `mode` picks for the time in seconds (nearest by default), returns
+    /// the time of that frame in seconds
+    #[wasm_bindgen(js_name = seekToTime)]
+    pub fn seek_to_time(&mut self, seconds: f64, mode: Option<SeekMode>, decoder: &VideoDecoder) -> Option<f64> {
+        let mode = mode.map(Into::into).unwrap_or(video::frames::SeekMode::Nearest);
+        let frame = self.source.with_frames(|frames| frames.seek_frame(to_micros(seconds), mode))?;
+
+        Some(self.seek(frame, decoder) / MICROS_PER_SECOND)
+    }
 
-            self.current_frame = idx;
-            self.render(decoder);
-            self.first_render = false;
+    /// Decodes the frames shown from `start` until `end` in seconds
+    #[wasm_bindgen(js_name = decodeTimeRange)]
+    pub fn decode_time_range(&mut self, start: f64, end: f64, decoder: &VideoDecoder) -> usize {
+        let range = self.source.with_frames(|frames| {
+            let from = frames.frame_at(to_micros(start))?;
+            // The frames that start before `end`
+            let to = frames.frame_at(to_micros(end) - 1)?;
+            Some((from, to)).filter(|(from, to)| from <= to && start < end)
+        });
+
+        match range {
+            Some((from, to)) => self.decode(from, to, decoder),
+            None => 0,
         }
-        
-        self.current_timestamp()
     }
 
-    fn current_timestamp(&self) -> u32 {
-        let frame = self.keyframes.get(self.current_frame).map(|f| f.timestamp).unwrap_or(0);
+    /// Decodes only keyframes, for thumbnails with a decoder of their own: every `stride`th frame
+    /// presented from `from` up to and including `to` is snapped to the nearest keyframe. Returns
+    /// the presentation indices of the decoded keyframes.
+    #[wasm_bindgen(js_name = decodeKeyframes)]
+    pub fn decode_keyframes(&mut self, from: usize, to: usize, stride: usize, decoder: &VideoDecoder) -> Vec<usize> {
+        let mut keyframes = self.source.with_frames(|frames| frames.nearest_keyframes(from, to, stride));
 
-        frame as u32
+        // Keyframes don't depend on other frames, the state of the main decoder is left alone
+        keyframes.retain(|idx| match self.source.with_frames(|frames| frames.presentation_to_decode(*idx)) {
+            Some(frame) => self.render(frame, decoder),
+            None => false,
+        });
+
+        keyframes
     }
 
-    fn skip_to_keyframe(&self, frame: usize) -> usize {
-        match frame.cmp(&self.current_frame) {
-            Ordering::Equal => self.current_frame,
-            Ordering::Greater => {
-                let next = self.keyframes.find_prev_key_frame_before(frame).unwrap_or(self.current_frame);
-                if next < self.current_frame {
-                    self.current_frame + 1
-                } else {
-                    next
-                }
-            },
-            Ordering::Less => self.keyframes.find_prev_key_frame_before(frame).unwrap_or(0),
+    /// The group of pictures (the frames from one keyframe up to the next) the frame is decoded in
+    #[wasm_bindgen(js_name = gopOf)]
+    pub fn gop_of(&self, frame: usize) -> Option<usize> {
+        self.source.with_frames(|frames| frames.gop_of(frame))
+    }
+
+    /// The first and last frame of a group of pictures
+    #[wasm_bindgen(js_name = gopRange)]
+    pub fn gop_range(&self, gop: usize) -> Option<Vec<usize>> {
+        let frames = self.source.with_frames(|frames| frames.gop_frames(gop));
+
+        Some(vec![*frames.first()?, *frames.last()?])
+    }
+
+    #[wasm_bindgen(js_name = gopCount)]
+    pub fn gop_count(&self) -> usize {
+        self.source.with_frames(|frames| frames.gop_count())
+    }
+
+    /// Decodes a whole group of pictures at once and returns the frames it outputs. Playing in
+    /// reverse shows these from last to first and then decodes `gop - 1`, instead of decoding
+    /// from the keyframe again for every frame.
+    #[wasm_bindgen(js_name = decodeGop)]
+    pub fn decode_gop(&mut self, gop: usize, decoder: &VideoDecoder) -> Vec<usize> {
+        let (range, frames) = self.source.with_frames(|frames| (frames.gop_decode_range(gop), frames.gop_frames(gop)));
+        let (start, end) = match range {
+            Some(range) => range,
+            None => return Vec::new(),
+        };
+
+        for idx in start..=end {
+            self.feed(idx, decoder);
+        }
+
+        frames
+    }
+
+    fn read_headers(&mut self) -> Result<(), JsValue> {
+        if self.config.is_some() {
+            return Ok(());
+        }
+
+        // Codec parameters the configuration lacks are read from the first keyframe, like those of
+        // VP9 in Matroska without CodecPrivate or of AV1 without configOBUs
+        if let Source::Stream(stream) = &self.source {
+            if !stream.ended() && stream.file().is_some_and(|file| file.needs_keyframe()) {
+                return Ok(());
+            }
+        }
+
+        let headers = self.source.with_file(|file| match file {
+            Some(file) => Ok(Some((file.duration()?, file.frame_rate(), DecoderConfig::read(file)?))),
+            None => Ok::<_, video::DemuxError>(None),
+        })?;
+
+        if let Some((duration, frame_rate, config)) = headers {
+            self.duration = duration;
+            self.frame_rate = frame_rate;
+            self.config = Some(config);
+        }
+
+        Ok(())
+    }
+
+    fn from_source(source: Source) -> Self {
+        Demuxer {
+            source,
+            chunks: ChunkCache::new(DEFAULT_CHUNK_CACHE_SIZE),
+            decoder_state: DecoderState::Reset,
+            duration: 0.0,
+            frame_rate: None,
+            config: None,
         }
     }
 
-    fn render(&self, decoder: &VideoDecoder) -> bool {
-        if let Some(frame) = self.keyframes.get(self.current_frame) {
-            console_log!("idx: {}, ts: {}, keyframe: {:?}", self.current_frame, frame.timestamp, frame.keyframe);
-            decoder.decode(&frame.chunk);
-            return true;
+    fn stream(&mut self) -> Result<&mut VideoStream, JsValue> {
+        match &mut self.source {
+            Source::Stream(stream) => Ok(stream),
+            Source::Range(..) => Err(JsError::new("Data can only be appended to a streaming demuxer").into()),
         }
+    }
 
-        console_error!("Could not render frame: {}", self.current_frame);
+    fn current_timestamp(&self) -> f64 {
+        let frame = match self.decoder_state {
+            DecoderState::Decoded(idx) => self.source.with_frames(|frames| frames.get(idx).map(|f| f.timestamp).unwrap_or(0)),
+            DecoderState::Reset => 0,
+        };
+
+        frame as f64
+    }
 
-        false
+    /// Decodes a frame with the decoder of `decode` & `seek`, a frame that couldn't be decoded
+    /// leaves it expecting a keyframe
+    fn feed(&mut self, idx: usize, decoder: &VideoDecoder) {
+        self.decoder_state = match self.render(idx, decoder) {
+            true => DecoderState::Decoded(idx),
+            false => DecoderState::Reset,
+        };
+    }
+
+    fn render(&mut self, idx: usize, decoder: &VideoDecoder) -> bool {
+        let chunks = &mut self.chunks;
+
+        self.source.with_frame(idx, |frame| {
+            if let Some((frame, bytes)) = frame {
+                console_log!("idx: {}, ts: {}, keyframe: {:?}", idx, frame.timestamp, frame.keyframe);
+                match chunks.get(idx, frame, bytes) {
+                    Ok(chunk) => decoder.decode(&chunk),
+                    Err(err) => {
+                        console_error!("Could not create chunk for frame {}: {}", idx, video::DemuxError::from(err));
+                        return false;
+                    },
+                }
+                return true;
+            }
+
+            console_error!("Could not render frame: {}", idx);
+
+            false
+        })
     }
 }
 
+#[cfg(feature = "web")]
 #[wasm_bindgen]
 #[derive(Copy, Clone, Debug)]
 pub enum ContainerFormat {
     Mkv = "mkv",
     Mp4 = "mp4",
+    Ivf = "ivf",
+    MpegTs = "ts",
+    Raw = "raw",
 }
 
+#[cfg(feature = "web")]
+const MICROS_PER_SECOND: f64 = 1_000_000.0;
+
+#[cfg(feature = "web")]
+fn to_micros(seconds: f64) -> i64 {
+    (seconds * MICROS_PER_SECOND).round() as i64
+}
+
+#[cfg(feature = "web")]
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug)]
+pub enum SeekMode {
+    Nearest = "nearest",
+    PreviousKeyframe = "previousKeyframe",
+    NextKeyframe = "nextKeyframe",
+    NearestKeyframe = "nearestKeyframe",
+}
+
+#[cfg(feature = "web")]
+impl From<SeekMode> for video::frames::SeekMode {
+    fn from(value: SeekMode) -> Self {
+        match value {
+            SeekMode::PreviousKeyframe => video::frames::SeekMode::PreviousKeyframe,
+            SeekMode::NextKeyframe => video::frames::SeekMode::NextKeyframe,
+            SeekMode::NearestKeyframe => video::frames::SeekMode::NearestKeyframe,
+            _ => video::frames::SeekMode::Nearest,
+        }
+    }
+}
+
+#[cfg(feature = "web")]
+impl From<video::format::Format> for ContainerFormat {
+    fn from(value: video::format::Format) -> Self {
+        match value {
+            video::format::Format::Matroska => ContainerFormat::Mkv,
+            video::format::Format::IsoBmff => ContainerFormat::Mp4,
+            video::format::Format::Ivf => ContainerFormat::Ivf,
+            video::format::Format::MpegTs => ContainerFormat::MpegTs,
+            video::format::Format::AnnexB | video::format::Format::Obu => ContainerFormat::Raw,
+        }
+    }
+}
+
+#[cfg(feature = "web")]
 impl From<video::DemuxError> for JsValue {
     fn from(value: video::DemuxError) -> Self {
         JsError::new(&value.to_string()).into()
     }
 }
 
+#[cfg(feature = "web")]
 #[wasm_bindgen]
 pub fn load(buffer: ArrayBuffer, format: ContainerFormat) -> Result<Demuxer, JsValue> {
-    let buffer = js_sys::Uint8Array::new(&buffer).to_vec();
-    let mut file: Box<dyn video::VideoFile> = match format {
-        ContainerFormat::Mkv => Box::new(video::mkv::MkvVideoFile::init(buffer)?),
-        ContainerFormat::Mp4 => Box::new(video::mp4::Mp4VideoFile::init(buffer)?),
-        format => return Err(JsError::new(&format!("Invalid container format: {format:?}")).into()),
+    let mut demuxer = Demuxer::new(Some(format))?;
+    demuxer.append(&js_sys::Uint8Array::new(&buffer).to_vec())?;
+    demuxer.end_of_stream()?;
+
+    console_log!("Demuxed frames size: {} mb", demuxer.source.with_frames(|frames| frames.total_size()) as f64 * 0.000001);
+
+    Ok(demuxer)
+}
+
+/// Loads a file through `reader` instead of downloading it completely, only the headers are read
+/// up front and frames are read with `Demuxer.fetch`
+#[cfg(feature = "web")]
+#[wasm_bindgen(js_name = loadRange)]
+pub async fn load_range(reader: RangeReader, format: Option<ContainerFormat>) -> Result<Demuxer, JsValue> {
+    let size = reader.size() as usize;
+    let start = reader.read_range(0, video::format::SNIFF_SIZE.min(size)).await?;
+    let format = match format {
+        Some(ContainerFormat::Mkv) => Format::Matroska,
+        Some(ContainerFormat::Mp4) => Format::IsoBmff,
+        Some(format) => return Err(JsError::new(&format!("Unsupported container format: {format:?}")).into()),
+        None => video::format::detect(&start)?,
     };
+    let mut file = RangeFile::new(format, size)?;
+    file.insert(0, &start);
+
+    while let Some((offset, length)) = file.header_read()? {
+        let bytes = reader.read_range(offset, length).await?;
+        file.insert(offset, &bytes);
+    }
+
+    let mut demuxer = Demuxer::from_source(Source::Range(Rc::new(RefCell::new(file)), reader));
+    demuxer.read_headers()?;
+
+    Ok(demuxer)
+}
+
+#[cfg(feature = "web")]
+fn sniff(buffer: &ArrayBuffer) -> Result<video::format::Format, video::DemuxError> {
+    let length = buffer.byte_length().min(video::format::SNIFF_SIZE as u32);
+    let start = js_sys::Uint8Array::new_with_byte_offset_and_length(buffer, 0, length).to_vec();
+
+    video::format::detect(&start)
+}
 
-    let codec = file.codec();
-    let coded_width = file.coded_width()?;
-    let coded_height = file.coded_height()?;
-    let duration = file.duration()?;
-    let keyframes = file.keyframes()?;
-
-    console_log!("Demuxed frames size: {} mb", keyframes.total_size() as f64 * 0.000001);
-
-    Ok(Demuxer {
-        first_render: true,
-        keyframes,
-        current_frame: 0,
-        coded_width,
-        coded_height,
-        duration,
-        codec,
-    })
+/// Recognizes the container of a file from its first bytes
+#[cfg(feature = "web")]
+#[wasm_bindgen(js_name = detectFormat)]
+pub fn detect_format(buffer: &ArrayBuffer) -> Result<ContainerFormat, JsValue> {
+    Ok(sniff(buffer)?.into())
+}
+
+/// Loads a file without knowing its container format up front
+#[cfg(feature = "web")]
+#[wasm_bindgen(js_name = loadAuto)]
+pub fn load_auto(buffer: ArrayBuffer) -> Result<Demuxer, JsValue> {
+    match sniff(&buffer)? {
+        video::format::Format::Matroska => load(buffer, ContainerFormat::Mkv),
+        video::format::Format::IsoBmff => load(buffer, ContainerFormat::Mp4),
+        format => Err(JsError::new(&format!("{format} files are not supported")).into()),
+    }
 }
@@ -1,9 +1,11 @@
+#[cfg(feature = "web")]
 use wasm_bindgen::prelude::*;
 
 pub const LOG: bool = false;
 pub const WARN: bool = true;
 pub const ERROR: bool = true;
 
+#[cfg(feature = "web")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
@@ -16,6 +18,21 @@ extern "C" {
     pub fn error(s: &str);
 }
 
+#[cfg(not(feature = "web"))]
+pub fn log(s: &str) {
+    println!("{s}");
+}
+
+#[cfg(not(feature = "web"))]
+pub fn warn(s: &str) {
+    eprintln!("{s}");
+}
+
+#[cfg(not(feature = "web"))]
+pub fn error(s: &str) {
+    eprintln!("{s}");
+}
+
 #[macro_export]
 macro_rules! console_log {
     ($($arg:tt)*) => (
@@ -0,0 +1,90 @@
+use std::{cell::RefCell, rc::Rc};
+use js_sys::{Promise, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+
+use crate::video::{frames::{FrameCache, FrameCacheStore}, range::RangeFile, stream::VideoStream, VideoFile};
+
+#[wasm_bindgen]
+extern "C" {
+    /// Reads parts of a file, e.g. with HTTP range requests:
+    /// `{ size: number, read(offset: number, length: number): Promise<Uint8Array> }`
+    #[derive(Clone)]
+    pub type RangeReader;
+
+    #[wasm_bindgen(method, getter)]
+    pub fn size(this: &RangeReader) -> f64;
+
+    #[wasm_bindgen(method, catch)]
+    fn read(this: &RangeReader, offset: f64, length: f64) -> Result<Promise, JsValue>;
+}
+
+impl RangeReader {
+    pub async fn read_range(&self, offset: usize, length: usize) -> Result<Vec<u8>, JsValue> {
+        let bytes = JsFuture::from(self.read(offset as f64, length as f64)?).await?;
+
+        Ok(Uint8Array::new(&bytes).to_vec())
+    }
+}
+
+/// Where the demuxer gets the file from: appended bytes, or a reader that is asked for the parts
+/// that are needed.
+pub enum Source {
+    Stream(VideoStream),
+    // Shared with the futures that fetch frames
+    Range(Rc<RefCell<RangeFile>>, RangeReader),
+}
+
+impl Source {
+    pub fn with_file<T>(&self, f: impl FnOnce(Option<&dyn VideoFile>) -> T) -> T {
+        match self {
+            Source::Stream(stream) => f(stream.file()),
+            Source::Range(file, _) => f(file.borrow().file()),
+        }
+    }
+
+    pub fn with_frames<T>(&self, f: impl FnOnce(&FrameCacheStore) -> T) -> T {
+        match self {
+            Source::Stream(stream) => f(stream.frames()),
+            Source::Range(file, _) => f(file.borrow().frames()),
+        }
+    }
+
+    /// Calls `f` with the frame & its bytes, when they have been loaded
+    pub fn with_frame<T>(&self, idx: usize, f: impl FnOnce(Option<(&FrameCache, Option<&[u8]>)>) -> T) -> T {
+        match self {
+            Source::Stream(stream) => {
+                let frame = stream.frames().get(idx);
+                f(frame.map(|frame| (frame, frame.data(stream.buffer()))))
+            },
+            Source::Range(file, _) => {
+                let file = file.borrow();
+                let frame = file.frames().get(idx);
+                f(frame.map(|frame| (frame, file.ranges().get(frame.offset, frame.size as usize).ok())))
+            },
+        }
+    }
+
+    /// Fetches the bytes of the frames `from` up to and including `to`, when they are read by range
+    pub fn fetch(&self, from: usize, to: usize) -> Promise {
+        let (file, reader) = match self {
+            Source::Range(file, reader) => (file.clone(), reader.clone()),
+            Source::Stream(_) => return Promise::resolve(&JsValue::UNDEFINED),
+        };
+
+        future_to_promise(async move {
+            loop {
+                // The file can't stay borrowed while waiting for the reader
+                let next = file.borrow_mut().frames_read(from, to)?;
+
+                match next {
+                    Some((offset, length)) => {
+                        let bytes = reader.read_range(offset, length).await?;
+                        file.borrow_mut().insert(offset, &bytes);
+                    },
+                    None => return Ok(JsValue::UNDEFINED),
+                }
+            }
+        })
+    }
+}
@@ -1,4 +1,7 @@
-use super::CodecPrivate;
+use super::{CodecPrivate, util::Bits, color::ColorSpace};
+
+const OBU_SEQUENCE_HEADER: u8 = 1;
+const UNSPECIFIED: u8 = 2;
 
 pub struct Av1 {
     pub seq_profile: u8,
@@ -6,13 +9,43 @@ pub struct Av1 {
     pub seq_tier_0: bool,
     pub high_bitdepth: bool,
     pub twelve_bit: bool,
+    /// From the configOBUs, or the first temporal unit when they don't have it
+    pub sequence_header: Option<SequenceHeader>,
 }
 
 impl Av1 {
+    // https://aomediacodec.github.io/av1-isobmff/#av1codecconfigurationbox-syntax
+    // Matroska stores the same record as CodecPrivate
+    pub fn from(av1c: &[u8]) -> Result<Self, String> {
+        let mut bits = Bits::new(av1c);
+        bits.skip(1, "marker")?;
+        bits.skip(7, "version")?;
+        let seq_profile = bits.read_u8(3, "seq_profile")?;
+        let seq_level_idx_0 = bits.read_u8(5, "seq_level_idx_0")?;
+        let seq_tier_0 = bits.read_bool("seq_tier_0")?;
+        let high_bitdepth = bits.read_bool("high_bitdepth")?;
+        let twelve_bit = bits.read_bool("twelve_bit")?;
+        // The configOBUs follow the 4 bytes of the record
+        let sequence_header = SequenceHeader::find(av1c.get(4..).unwrap_or_default()).ok().flatten();
+        Ok(Self {
+            seq_profile,
+            seq_level_idx_0,
+            seq_tier_0,
+            high_bitdepth,
+            twelve_bit,
+            sequence_header,
+        })
+    }
+
+    /// Uses the sequence header of a temporal unit when the configOBUs don't have one
+    pub fn or_sequence_header(self, sequence_header: Option<SequenceHeader>) -> Self {
+        Self { sequence_header: self.sequence_header.or(sequence_header), ..self }
+    }
+
     fn tier(&self) -> String {
         match self.seq_tier_0 {
-            true => "M".to_string(),
-            false => "H".to_string(),
+            true => "H".to_string(),
+            false => "M".to_string(),
         }
     }
 
@@ -34,7 +67,252 @@ impl Av1 {
 }
 
 impl CodecPrivate for Av1 {
+    // https://aomediacodec.github.io/av1-isobmff/#codecsparam, the optional fields need the
+    // sequence header
     fn to_codec_string(&self) -> String {
-        format!("av01.{}.{:02}{}.{:02}", self.seq_profile, self.seq_level_idx_0, self.tier(), self.bit_depth())
+        let short = format!("av01.{}.{:02}{}.{:02}", self.seq_profile, self.seq_level_idx_0, self.tier(), self.bit_depth());
+        let header = match &self.sequence_header {
+            Some(header) => header,
+            None => return short,
+        };
+
+        // av01.P.LLT.DD.M.CCC.cp.tc.mc.F
+        let color_space = header.color_space;
+        format!(
+            "{short}.{}.{}{}{}.{:02}.{:02}.{:02}.{}",
+            header.mono_chrome as u8,
+            header.subsampling_x as u8,
+            header.subsampling_y as u8,
+            header.chroma_sample_position,
+            color_space.primaries.unwrap_or(UNSPECIFIED),
+            color_space.transfer.unwrap_or(UNSPECIFIED),
+            color_space.matrix.unwrap_or(UNSPECIFIED),
+            color_space.full_range.unwrap_or(false) as u8,
+        )
+    }
+}
+
+/// Reads a leb128() value, returns it with the number of bytes it takes
+fn leb128(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0;
+    for (idx, byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7F) as u64) << (idx * 7);
+        if byte & 0x80 == 0 {
+            return Some((value, idx + 1));
+        }
+    }
+
+    None
+}
+
+/// The fields of a sequence header OBU that describe the stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceHeader {
+    pub seq_profile: u8,
+    pub seq_level_idx_0: u8,
+    pub seq_tier_0: bool,
+    pub bit_depth: u8,
+    pub mono_chrome: bool,
+    pub subsampling_x: bool,
+    pub subsampling_y: bool,
+    pub chroma_sample_position: u8,
+    pub color_space: ColorSpace,
+}
+
+impl SequenceHeader {
+    /// Finds the sequence header in a sequence of OBUs, like the configOBUs of an av1C record or
+    /// a temporal unit
+    pub fn find(mut obus: &[u8]) -> Result<Option<Self>, String> {
+        while let Some(header) = obus.first() {
+            let obu_type = (header >> 3) & 0x0F;
+            let has_extension = header & 0x04 != 0;
+            let has_size = header & 0x02 != 0;
+
+            let header_size = 1 + has_extension as usize;
+            let (size, size_length) = match has_size {
+                true => leb128(obus.get(header_size..).ok_or("Truncated OBU header")?).ok_or("Invalid OBU size")?,
+                false => ((obus.len() - header_size.min(obus.len())) as u64, 0),
+            };
+            let start = header_size + size_length;
+            let payload = obus.get(start..start + size as usize).ok_or("Truncated OBU")?;
+
+            if obu_type == OBU_SEQUENCE_HEADER {
+                return Self::from(payload).map(Some);
+            }
+
+            obus = &obus[start + size as usize..];
+        }
+
+        Ok(None)
+    }
+
+    // https://aomediacodec.github.io/av1-spec/#sequence-header-obu-syntax
+    pub fn from(payload: &[u8]) -> Result<Self, String> {
+        let mut bits = Bits::new(payload);
+        let seq_profile = bits.read_u8(3, "seq_profile")?;
+        bits.skip(1, "still_picture")?;
+        let reduced_still_picture_header = bits.read_bool("reduced_still_picture_header")?;
+
+        let (seq_level_idx_0, seq_tier_0) = match reduced_still_picture_header {
+            true => (bits.read_u8(5, "seq_level_idx")?, false),
+            false => Self::read_operating_points(&mut bits)?,
+        };
+
+        let frame_width_bits = bits.read_u8(4, "frame_width_bits_minus_1")? + 1;
+        let frame_height_bits = bits.read_u8(4, "frame_height_bits_minus_1")? + 1;
+        bits.read_u32(frame_width_bits, "max_frame_width_minus_1")?;
+        bits.read_u32(frame_height_bits, "max_frame_height_minus_1")?;
+        if !reduced_still_picture_header && bits.read_bool("frame_id_numbers_present_flag")? {
+            bits.skip(4, "delta_frame_id_length_minus_2")?;
+            bits.skip(3, "additional_frame_id_length_minus_1")?;
+        }
+        bits.skip(3, "use_128x128_superblock, enable_filter_intra & enable_intra_edge_filter")?;
+
+        if !reduced_still_picture_header {
+            bits.skip(4, "enable_interintra_compound, enable_masked_compound, enable_warped_motion & enable_dual_filter")?;
+            let enable_order_hint = bits.read_bool("enable_order_hint")?;
+            if enable_order_hint {
+                bits.skip(2, "enable_jnt_comp & enable_ref_frame_mvs")?;
+            }
+            let seq_force_screen_content_tools = match bits.read_bool("seq_choose_screen_content_tools")? {
+                true => 2,
+                false => bits.read_u8(1, "seq_force_screen_content_tools")?,
+            };
+            if seq_force_screen_content_tools > 0 && !bits.read_bool("seq_choose_integer_mv")? {
+                bits.skip(1, "seq_force_integer_mv")?;
+            }
+            if enable_order_hint {
+                bits.skip(3, "order_hint_bits_minus_1")?;
+            }
+        }
+
+        bits.skip(3, "enable_superres, enable_cdef & enable_restoration")?;
+
+        Self::read_color_config(&mut bits, seq_profile, seq_level_idx_0, seq_tier_0)
+    }
+
+    /// Returns the level & tier of the first operating point
+    fn read_operating_points(bits: &mut Bits) -> Result<(u8, bool), String> {
+        let mut buffer_delay_length = 0;
+        let decoder_model_info_present_flag = match bits.read_bool("timing_info_present_flag")? {
+            true => {
+                bits.skip(32, "num_units_in_display_tick")?;
+                bits.skip(32, "time_scale")?;
+                if bits.read_bool("equal_picture_interval")? {
+                    bits.read_uvlc("num_ticks_per_picture_minus_1")?;
+                }
+
+                let present = bits.read_bool("decoder_model_info_present_flag")?;
+                if present {
+                    buffer_delay_length = bits.read_u8(5, "buffer_delay_length_minus_1")? + 1;
+                    bits.skip(32, "num_units_in_decoding_tick")?;
+                    bits.skip(10, "buffer_removal_time_length_minus_1 & frame_presentation_time_length_minus_1")?;
+                }
+                present
+            },
+            false => false,
+        };
+        let initial_display_delay_present_flag = bits.read_bool("initial_display_delay_present_flag")?;
+
+        let mut first = None;
+        for _ in 0..=bits.read_u8(5, "operating_points_cnt_minus_1")? {
+            bits.skip(12, "operating_point_idc")?;
+            let seq_level_idx = bits.read_u8(5, "seq_level_idx")?;
+            let seq_tier = seq_level_idx > 7 && bits.read_bool("seq_tier")?;
+
+            if decoder_model_info_present_flag && bits.read_bool("decoder_model_present_for_this_op")? {
+                bits.read_u32(buffer_delay_length, "decoder_buffer_delay")?;
+                bits.read_u32(buffer_delay_length, "encoder_buffer_delay")?;
+                bits.skip(1, "low_delay_mode_flag")?;
+            }
+            if initial_display_delay_present_flag && bits.read_bool("initial_display_delay_present_for_this_op")? {
+                bits.skip(4, "initial_display_delay_minus_1")?;
+            }
+
+            first.get_or_insert((seq_level_idx, seq_tier));
+        }
+
+        Ok(first.unwrap_or_default())
+    }
+
+    // https://aomediacodec.github.io/av1-spec/#color-config-syntax
+    fn read_color_config(bits: &mut Bits, seq_profile: u8, seq_level_idx_0: u8, seq_tier_0: bool) -> Result<Self, String> {
+        let high_bitdepth = bits.read_bool("high_bitdepth")?;
+        let bit_depth = match (seq_profile, high_bitdepth) {
+            (2, true) if bits.read_bool("twelve_bit")? => 12,
+            (_, true) => 10,
+            (_, false) => 8,
+        };
+        let mono_chrome = seq_profile != 1 && bits.read_bool("mono_chrome")?;
+        let (primaries, transfer, matrix) = match bits.read_bool("color_description_present_flag")? {
+            true => (bits.read_u8(8, "color_primaries")?, bits.read_u8(8, "transfer_characteristics")?, bits.read_u8(8, "matrix_coefficients")?),
+            false => (2, 2, 2),
+        };
+
+        let mut header = Self {
+            seq_profile,
+            seq_level_idx_0,
+            seq_tier_0,
+            bit_depth,
+            mono_chrome,
+            subsampling_x: true,
+            subsampling_y: true,
+            chroma_sample_position: 0,
+            color_space: ColorSpace::new(primaries, transfer, matrix, false),
+        };
+
+        // sRGB is always full range 4:4:4
+        if !mono_chrome && (primaries, transfer, matrix) == (1, 13, 0) {
+            header.color_space.full_range = Some(true);
+            header.subsampling_x = false;
+            header.subsampling_y = false;
+            return Ok(header);
+        }
+
+        header.color_space.full_range = Some(bits.read_bool("color_range")?);
+        if mono_chrome {
+            return Ok(header);
+        }
+
+        (header.subsampling_x, header.subsampling_y) = match seq_profile {
+            0 => (true, true),
+            1 => (false, false),
+            _ if bit_depth == 12 => match bits.read_bool("subsampling_x")? {
+                true => (true, bits.read_bool("subsampling_y")?),
+                false => (false, false),
+            },
+            _ => (true, false),
+        };
+        if header.subsampling_x && header.subsampling_y {
+            header.chroma_sample_position = bits.read_u8(2, "chroma_sample_position")?;
+        }
+
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Av1, SequenceHeader};
+    use crate::video::CodecPrivate;
+
+    #[test]
+    fn it_reads_the_sequence_header_of_the_config_obus() {
+        // The av1C record of video_2s_30fps_640x480_av1.mp4
+        let av1c = [0x81, 0x24, 0x00, 0x00, 0x0A, 0x0D, 0x20, 0x00, 0x00, 0x24, 0xC4, 0xFF, 0xDF, 0x3F, 0xFE, 0x68, 0x08, 0x68, 0x01];
+        let header = SequenceHeader::find(&av1c[4..]).unwrap().unwrap();
+        assert_eq!((header.seq_profile, header.seq_level_idx_0, header.seq_tier_0, header.bit_depth), (1, 4, false, 8));
+        // 4:4:4 sRGB
+        assert_eq!((header.subsampling_x, header.subsampling_y), (false, false));
+        assert_eq!((header.color_space.transfer, header.color_space.full_range), (Some(13), Some(true)));
+        assert_eq!(SequenceHeader::find(&[]), Ok(None));
+
+        assert_eq!(Av1::from(&av1c).unwrap().to_codec_string(), "av01.1.04M.08.0.000.01.13.00.1");
+        // Without configOBUs
+        assert_eq!(Av1::from(&av1c[..4]).unwrap().to_codec_string(), "av01.1.04M.08");
+
+        // A temporal unit starts with a temporal delimiter
+        let temporal_unit = [&[0x12, 0x00], &av1c[4..]].concat();
+        assert_eq!(SequenceHeader::find(&temporal_unit), Ok(Some(header)));
     }
-}
\ No newline at end of file
+}
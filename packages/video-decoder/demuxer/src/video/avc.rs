@@ -0,0 +1,52 @@
+use super::CodecPrivate;
+
+/// The start of an `AVCDecoderConfigurationRecord` (avcC)
+#[derive(Debug, PartialEq, Eq)]
+pub struct Avc {
+    pub format: String,
+    pub profile: u8,
+    pub constraints: u8,
+    pub level: u8,
+}
+
+impl Avc {
+    // ISO/IEC 14496-15 5.3.3.1
+    pub fn from(format: &str, avcc: &[u8]) -> Result<Self, String> {
+        match avcc {
+            [1, profile, constraints, level, ..] => Ok(Self {
+                format: format.to_string(),
+                profile: *profile,
+                constraints: *constraints,
+                level: *level,
+            }),
+            [version, _, _, _, ..] => Err(format!("Unsupported avcC version {version}")),
+            _ => Err("avcC is too short".to_string()),
+        }
+    }
+}
+
+impl CodecPrivate for Avc {
+    fn to_codec_string(&self) -> String {
+        // cccc.PPCCLL
+        format!("{}.{:02X}{:02X}{:02X}", self.format, self.profile, self.constraints, self.level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::video::{avc::Avc, CodecPrivate};
+
+    #[test]
+    fn it_builds_the_codec_string_from_avcc() {
+        let avcc: &[u8] = &[
+            0x01, 0x64, 0x00, 0x1F, 0xFF, // version, High profile, no constraints, level 3.1, NAL length size 4
+            0xE1, 0x00, 0x04, 0x67, 0x64, 0x00, 0x1F, // 1 SPS
+            0x01, 0x00, 0x02, 0x68, 0xEB, // 1 PPS
+        ];
+
+        assert_eq!(Avc::from("avc1", avcc).unwrap().to_codec_string(), "avc1.64001F");
+        assert_eq!(Avc::from("avc3", &[1, 0x42, 0xE0, 0x1E]).unwrap().to_codec_string(), "avc3.42E01E");
+        assert_eq!(Avc::from("avc1", &[0, 0x42, 0xE0, 0x1E]), Err("Unsupported avcC version 0".to_string()));
+        assert_eq!(Avc::from("avc1", &[1, 0x42]), Err("avcC is too short".to_string()));
+    }
+}
@@ -0,0 +1,198 @@
+/// Colour description using the code points of ISO/IEC 23091-2 (ITU-T H.273), the same values are
+/// used by vpcC, colr (nclx), the Matroska Colour element and the codec bitstreams.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColorSpace {
+    pub primaries: Option<u8>,
+    pub transfer: Option<u8>,
+    pub matrix: Option<u8>,
+    pub full_range: Option<bool>,
+}
+
+const UNSPECIFIED: u8 = 2;
+
+/// The colour volume of the display the video was mastered on (SMPTE ST 2086). Chromaticity
+/// coordinates are CIE 1931 x & y, luminances in cd/m².
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MasteringDisplay {
+    pub red: (f64, f64),
+    pub green: (f64, f64),
+    pub blue: (f64, f64),
+    pub white_point: (f64, f64),
+    pub max_luminance: f64,
+    pub min_luminance: f64,
+}
+
+/// The brightest pixel & the brightest frame on average of the content in cd/m² (CTA-861.3)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    pub max_content_light_level: u16,
+    pub max_frame_average_light_level: u16,
+}
+
+/// Everything that is known about the colours of a video, the HDR metadata only describes what
+/// the content looks like and isn't needed to decode it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ColorInfo {
+    pub color_space: ColorSpace,
+    pub mastering_display: Option<MasteringDisplay>,
+    pub content_light_level: Option<ContentLightLevel>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl MasteringDisplay {
+    // https://aomediacodec.github.io/av1-isobmff/#mdcv, the primaries are in the order of HEVC:
+    // green, blue, red. Chromaticities are in 0.00002 units, luminances in 0.0001 cd/m².
+    pub fn from_mdcv(mdcv: &[u8]) -> Option<Self> {
+        let chromaticity = |idx: usize| Some((read_u16(mdcv, idx * 4)? as f64 * 0.00002, read_u16(mdcv, idx * 4 + 2)? as f64 * 0.00002));
+
+        Some(Self {
+            green: chromaticity(0)?,
+            blue: chromaticity(1)?,
+            red: chromaticity(2)?,
+            white_point: chromaticity(3)?,
+            max_luminance: read_u32(mdcv, 16)? as f64 * 0.0001,
+            min_luminance: read_u32(mdcv, 20)? as f64 * 0.0001,
+        })
+    }
+}
+
+impl ContentLightLevel {
+    pub fn from_clli(clli: &[u8]) -> Option<Self> {
+        Some(Self {
+            max_content_light_level: read_u16(clli, 0)?,
+            max_frame_average_light_level: read_u16(clli, 2)?,
+        })
+    }
+}
+
+impl ColorInfo {
+    /// Takes what is missing from `fallback`, like the colour of the codec configuration when the
+    /// container doesn't describe it
+    pub fn or(self, fallback: ColorInfo) -> Self {
+        Self {
+            color_space: self.color_space.or(fallback.color_space),
+            mastering_display: self.mastering_display.or(fallback.mastering_display),
+            content_light_level: self.content_light_level.or(fallback.content_light_level),
+        }
+    }
+}
+
+impl ColorSpace {
+    /// Treats the "unspecified" code points as missing
+    pub fn new(primaries: u8, transfer: u8, matrix: u8, full_range: bool) -> Self {
+        let specified = |value: u8| Some(value).filter(|value| *value != UNSPECIFIED);
+
+        Self {
+            primaries: specified(primaries),
+            transfer: specified(transfer),
+            matrix: specified(matrix),
+            full_range: Some(full_range),
+        }
+    }
+
+    /// From the body of a `colr` box, only the nclx type has code points
+    pub fn from_colr(colr: &[u8]) -> Option<Self> {
+        if colr.get(0..4)? != b"nclx" {
+            return None;
+        }
+
+        Some(Self::new(
+            read_u16(colr, 4)? as u8,
+            read_u16(colr, 6)? as u8,
+            read_u16(colr, 8)? as u8,
+            colr.get(10)? & 0x80 != 0,
+        ))
+    }
+
+    /// Takes the values that are missing from `fallback`
+    pub fn or(self, fallback: ColorSpace) -> Self {
+        Self {
+            primaries: self.primaries.or(fallback.primaries),
+            transfer: self.transfer.or(fallback.transfer),
+            matrix: self.matrix.or(fallback.matrix),
+            full_range: self.full_range.or(fallback.full_range),
+        }
+    }
+
+    // https://w3c.github.io/webcodecs/#enumdef-videocolorprimaries
+    pub fn primaries_name(&self) -> Option<&'static str> {
+        match self.primaries? {
+            1 => Some("bt709"),
+            5 => Some("bt470bg"),
+            6 => Some("smpte170m"),
+            9 => Some("bt2020"),
+            12 => Some("smpte432"),
+            _ => None,
+        }
+    }
+
+    // https://w3c.github.io/webcodecs/#enumdef-videotransfercharacteristics
+    pub fn transfer_name(&self) -> Option<&'static str> {
+        match self.transfer? {
+            // BT.2020 10 & 12 bit use the same transfer function as BT.709
+            1 | 14 | 15 => Some("bt709"),
+            6 => Some("smpte170m"),
+            8 => Some("linear"),
+            13 => Some("iec61966-2-1"),
+            16 => Some("pq"),
+            18 => Some("hlg"),
+            _ => None,
+        }
+    }
+
+    // https://w3c.github.io/webcodecs/#enumdef-videomatrixcoefficients
+    pub fn matrix_name(&self) -> Option<&'static str> {
+        match self.matrix? {
+            0 => Some("rgb"),
+            1 => Some("bt709"),
+            5 => Some("bt470bg"),
+            6 => Some("smpte170m"),
+            9 => Some("bt2020-ncl"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::video::color::{ColorSpace, ContentLightLevel, MasteringDisplay};
+
+    #[test]
+    fn it_maps_code_points_to_webcodecs_names() {
+        let hdr10 = ColorSpace::new(9, 16, 9, false);
+        assert_eq!(hdr10.primaries_name(), Some("bt2020"));
+        assert_eq!(hdr10.transfer_name(), Some("pq"));
+        assert_eq!(hdr10.matrix_name(), Some("bt2020-ncl"));
+
+        let unspecified = ColorSpace::new(2, 2, 2, false);
+        assert_eq!(unspecified, ColorSpace { primaries: None, transfer: None, matrix: None, full_range: Some(false) });
+        assert_eq!(unspecified.primaries_name(), None);
+    }
+
+    #[test]
+    fn it_reads_the_colour_boxes() {
+        let colr = [b'n', b'c', b'l', b'x', 0, 9, 0, 16, 0, 9, 0x80];
+        assert_eq!(ColorSpace::from_colr(&colr), Some(ColorSpace { primaries: Some(9), transfer: Some(16), matrix: Some(9), full_range: Some(true) }));
+        assert_eq!(ColorSpace::from_colr(b"prof"), None);
+
+        // P3 D65 primaries, 1000 & 0.0001 cd/m²
+        let mdcv: Vec<u8> = [13250u16, 34500, 7500, 3000, 34000, 16000, 15635, 16450]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .chain(10_000_000u32.to_be_bytes())
+            .chain(1u32.to_be_bytes())
+            .collect();
+        let mastering_display = MasteringDisplay::from_mdcv(&mdcv).unwrap();
+        assert_eq!(mastering_display.red, (34000.0 * 0.00002, 16000.0 * 0.00002));
+        assert_eq!(mastering_display.max_luminance.round(), 1000.0);
+
+        assert_eq!(ContentLightLevel::from_clli(&[0x03, 0xE8, 0x01, 0x90]), Some(ContentLightLevel { max_content_light_level: 1000, max_frame_average_light_level: 400 }));
+    }
+}
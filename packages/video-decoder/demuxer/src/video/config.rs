@@ -0,0 +1,35 @@
+use super::{VideoFile, color::ColorInfo, display::Orientation};
+
+/// Everything that is needed to configure a decoder for the video track of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecoderConfig {
+    pub codec: Option<String>,
+    pub coded_width: u32,
+    pub coded_height: u32,
+    pub display_width: u32,
+    pub display_height: u32,
+    pub pixel_aspect_ratio: Option<(u32, u32)>,
+    pub orientation: Orientation,
+    pub description: Option<Vec<u8>>,
+    pub color_info: ColorInfo,
+}
+
+impl DecoderConfig {
+    pub fn read(file: &dyn VideoFile) -> super::Result<Self> {
+        let coded_width = file.coded_width()?;
+        let coded_height = file.coded_height()?;
+        let (display_width, display_height) = file.display_size().unwrap_or((coded_width, coded_height));
+
+        Ok(Self {
+            codec: file.codec(),
+            coded_width,
+            coded_height,
+            display_width,
+            display_height,
+            pixel_aspect_ratio: file.pixel_aspect_ratio(),
+            orientation: file.orientation(),
+            description: file.description(),
+            color_info: file.color_info(),
+        })
+    }
+}
@@ -0,0 +1,82 @@
+use super::timebase::gcd;
+
+/// How the frames have to be turned to display them upright: first mirrored horizontally when
+/// `flip` is set, then rotated clockwise by `rotation` degrees (0, 90, 180 or 270).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Orientation {
+    pub rotation: u16,
+    pub flip: bool,
+}
+
+/// Rounds an angle in degrees to a quarter turn between 0 and 270
+fn quarter_turn(degrees: f64) -> u16 {
+    ((degrees / 90.0).round() as i64).rem_euclid(4) as u16 * 90
+}
+
+impl Orientation {
+    /// From the `a`, `b`, `c` & `d` values of an MP4 transformation matrix, which maps a point to
+    /// `(a * x + c * y, b * x + d * y)`
+    pub fn from_matrix(a: i32, b: i32, c: i32, d: i32) -> Self {
+        // A mirrored matrix has a negative determinant, without the flip its first row is the
+        // rotation
+        let flip = (a as i64 * d as i64) - (b as i64 * c as i64) < 0;
+        let (a, b) = match flip {
+            true => (-a, -b),
+            false => (a, b),
+        };
+
+        Self { rotation: quarter_turn((b as f64).atan2(a as f64).to_degrees()), flip }
+    }
+
+    /// From the pose of a Matroska Projection, the roll is a counter-clockwise rotation. A yaw of
+    /// 180° mirrors the frames horizontally, a pitch of 180° vertically.
+    pub fn from_projection(yaw: f64, pitch: f64, roll: f64) -> Self {
+        let yaw = quarter_turn(yaw);
+        let pitch = quarter_turn(pitch);
+        let rotation = quarter_turn(-roll);
+
+        match (yaw, pitch) {
+            (180, 0) => Self { rotation, flip: true },
+            // Mirroring vertically is mirroring horizontally & turning it upside down
+            (0, 180) => Self { rotation: (rotation + 180) % 360, flip: true },
+            // Turning both ways is the same as turning it upside down
+            (180, 180) => Self { rotation: (rotation + 180) % 360, flip: false },
+            _ => Self { rotation, flip: false },
+        }
+    }
+}
+
+/// Reduces a ratio, `None` for square pixels or an invalid ratio
+pub fn aspect_ratio(horizontal: u64, vertical: u64) -> Option<(u32, u32)> {
+    if horizontal == 0 || vertical == 0 || horizontal == vertical {
+        return None;
+    }
+
+    let divisor = gcd(horizontal, vertical);
+    Some(((horizontal / divisor) as u32, (vertical / divisor) as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aspect_ratio, Orientation};
+
+    const ONE: i32 = 0x10000;
+
+    #[test]
+    fn it_reads_the_orientation() {
+        assert_eq!(Orientation::from_matrix(ONE, 0, 0, ONE), Orientation::default());
+        assert_eq!(Orientation::from_matrix(0, ONE, -ONE, 0), Orientation { rotation: 90, flip: false });
+        assert_eq!(Orientation::from_matrix(-ONE, 0, 0, -ONE), Orientation { rotation: 180, flip: false });
+        assert_eq!(Orientation::from_matrix(0, -ONE, ONE, 0), Orientation { rotation: 270, flip: false });
+        assert_eq!(Orientation::from_matrix(-ONE, 0, 0, ONE), Orientation { rotation: 0, flip: true });
+        assert_eq!(Orientation::from_matrix(0, -ONE, -ONE, 0), Orientation { rotation: 90, flip: true });
+
+        assert_eq!(Orientation::from_projection(0.0, 0.0, -90.0), Orientation { rotation: 90, flip: false });
+        assert_eq!(Orientation::from_projection(0.0, 0.0, 90.0), Orientation { rotation: 270, flip: false });
+        assert_eq!(Orientation::from_projection(180.0, 0.0, 0.0), Orientation { rotation: 0, flip: true });
+        assert_eq!(Orientation::from_projection(0.0, 180.0, 0.0), Orientation { rotation: 180, flip: true });
+
+        assert_eq!(aspect_ratio(640, 480), Some((4, 3)));
+        assert_eq!(aspect_ratio(1, 1), None);
+    }
+}
@@ -0,0 +1,609 @@
+use std::convert::TryFrom;
+
+use super::range::{RangeBuffer, RangeError};
+
+// https://www.matroska.org/technical/elements.html
+const EBML: u32 = 0x1A45DFA3;
+const SEGMENT: u32 = 0x18538067;
+const SEEK_HEAD: u32 = 0x114D9B74;
+const SEEK: u32 = 0x4DBB;
+const SEEK_ID: u32 = 0x53AB;
+const SEEK_POSITION: u32 = 0x53AC;
+const INFO: u32 = 0x1549A966;
+const TRACKS: u32 = 0x1654AE6B;
+const CLUSTER: u32 = 0x1F43B675;
+const TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+const BLOCK_GROUP: u32 = 0xA0;
+const BLOCK: u32 = 0xA1;
+const BLOCK_DURATION: u32 = 0x9B;
+const REFERENCE_BLOCK: u32 = 0xFB;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const VIDEO: u32 = 0xE0;
+const PROJECTION: u32 = 0x7670;
+const PROJECTION_TYPE: u32 = 0x7671;
+const PROJECTION_POSE_YAW: u32 = 0x7673;
+const PROJECTION_POSE_PITCH: u32 = 0x7674;
+const PROJECTION_POSE_ROLL: u32 = 0x7675;
+
+const UNKNOWN_SIZE: u64 = u64::MAX;
+
+struct ElementHeader {
+    id: u32,
+    data_offset: usize,
+    size: u64,
+}
+
+impl ElementHeader {
+    /// `None` for an unknown size, or a size that doesn't fit in memory
+    fn end(&self) -> Option<usize> {
+        match self.size {
+            UNKNOWN_SIZE => None,
+            size => usize::try_from(size).ok().and_then(|size| self.data_offset.checked_add(size)),
+        }
+    }
+}
+
+fn vint_length(first: u8) -> Result<usize, String> {
+    match first.leading_zeros() {
+        8 => Err("Invalid variable size integer".to_string()),
+        zeros => Ok(zeros as usize + 1),
+    }
+}
+
+/// Reads an element id, the marker bits are kept as they are part of the id.
+fn read_id(data: &[u8], offset: usize) -> Result<Option<(u32, usize)>, String> {
+    let first = match data.get(offset) {
+        Some(first) => *first,
+        None => return Ok(None),
+    };
+    let length = vint_length(first)?;
+    if length > 4 {
+        return Err(format!("Invalid element id length {length}"));
+    }
+
+    match data.get(offset..offset + length) {
+        Some(bytes) => Ok(Some((bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u32), length))),
+        None => Ok(None),
+    }
+}
+
+/// Reads a variable size integer with the marker bit removed, a value with all bits set is
+/// returned as [`UNKNOWN_SIZE`].
+fn read_vint(data: &[u8], offset: usize) -> Result<Option<(u64, usize)>, String> {
+    let first = match data.get(offset) {
+        Some(first) => *first,
+        None => return Ok(None),
+    };
+    let length = vint_length(first)?;
+    let bytes = match data.get(offset..offset + length) {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+
+    let mask = (1u64 << (7 * length)) - 1;
+    let value = bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u64) & mask;
+
+    if value == mask {
+        Ok(Some((UNKNOWN_SIZE, length)))
+    } else {
+        Ok(Some((value, length)))
+    }
+}
+
+fn read_header(data: &[u8], offset: usize) -> Result<Option<ElementHeader>, String> {
+    let (id, id_length) = match read_id(data, offset)? {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+    let (size, size_length) = match read_vint(data, offset + id_length)? {
+        Some(size) => size,
+        None => return Ok(None),
+    };
+
+    Ok(Some(ElementHeader { id, data_offset: offset + id_length + size_length, size }))
+}
+
+fn read_uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |acc, b| (acc << 8) | *b as u64)
+}
+
+fn read_float(data: &[u8]) -> Result<f64, String> {
+    match data.len() {
+        0 => Ok(0.0),
+        4 => Ok(f32::from_bits(read_uint(data) as u32) as f64),
+        8 => Ok(f64::from_bits(read_uint(data))),
+        length => Err(format!("Invalid float length {length}")),
+    }
+}
+
+/// The ids & bodies of the elements in `data`, up to the first one of unknown size or that is
+/// truncated. A truncated segment is returned with the part of it that is there.
+fn children(data: &[u8]) -> Result<Vec<(u32, &[u8])>, String> {
+    let mut elements = Vec::new();
+    let mut position = 0;
+
+    while let Some(header) = read_header(data, position)? {
+        let end = match header.end() {
+            Some(end) if end <= data.len() => end,
+            _ if header.id == SEGMENT => data.len(),
+            _ => break,
+        };
+        let body = match data.get(header.data_offset..end) {
+            Some(body) => body,
+            None => break,
+        };
+
+        elements.push((header.id, body));
+        position = end;
+    }
+
+    Ok(elements)
+}
+
+fn child<'a>(elements: &[(u32, &'a [u8])], id: u32) -> Option<&'a [u8]> {
+    elements.iter().find(|(found, _)| *found == id).map(|(_, body)| *body)
+}
+
+/// The yaw, pitch & roll in degrees of the Projection of a track, from the start of a file.
+/// Only rectangular projections are returned, the others map the frames onto a sphere.
+pub fn projection_pose(data: &[u8], track_number: u64) -> Result<Option<(f64, f64, f64)>, String> {
+    let segment = match child(&children(data)?, SEGMENT) {
+        Some(segment) => segment,
+        None => return Ok(None),
+    };
+    let tracks = match child(&children(segment)?, TRACKS) {
+        Some(tracks) => tracks,
+        None => return Ok(None),
+    };
+
+    for (id, entry) in children(tracks)? {
+        if id != TRACK_ENTRY {
+            continue;
+        }
+
+        let entry = children(entry)?;
+        if child(&entry, TRACK_NUMBER).map(read_uint) != Some(track_number) {
+            continue;
+        }
+
+        let projection = match child(&entry, VIDEO).map(children).transpose()?.and_then(|video| child(&video, PROJECTION)) {
+            Some(projection) => children(projection)?,
+            None => return Ok(None),
+        };
+        if child(&projection, PROJECTION_TYPE).map(read_uint).unwrap_or(0) != 0 {
+            return Ok(None);
+        }
+
+        let pose = |id| child(&projection, id).map(read_float).unwrap_or(Ok(0.0));
+        return Ok(Some((pose(PROJECTION_POSE_YAW)?, pose(PROJECTION_POSE_PITCH)?, pose(PROJECTION_POSE_ROLL)?)));
+    }
+
+    Ok(None)
+}
+
+/// Copies the EBML header, Info & Tracks into a file of their own once the first cluster has been
+/// reached, or returns `None` while they aren't complete yet.
+///
+/// Everything else is left out on purpose: the SeekHead usually points to Cues at the end of the
+/// file, which the matroska demuxer would try to read while opening the file.
+pub fn header(data: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    let ebml = match read_header(data, 0)? {
+        Some(header) if header.id == EBML => header,
+        Some(_) => return Err("Missing EBML header".to_string()),
+        None => return Ok(None),
+    };
+    let ebml_end = ebml.end().ok_or("EBML header has an unknown size")?;
+    let segment = match read_header(data, ebml_end)? {
+        Some(header) if header.id == SEGMENT => header,
+        Some(_) => return Err("Missing segment".to_string()),
+        None => return Ok(None),
+    };
+
+    let mut children = Vec::new();
+    let mut position = segment.data_offset;
+
+    loop {
+        let header = match read_header(data, position)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        if header.id == CLUSTER {
+            break;
+        }
+
+        let end = header.end().ok_or(format!("Element {:#X} has an unknown size", header.id))?;
+        if end > data.len() {
+            return Ok(None);
+        }
+
+        if header.id == INFO || header.id == TRACKS {
+            children.extend_from_slice(&data[position..end]);
+        }
+
+        position = end;
+    }
+
+    Ok(Some(header_file(&data[..ebml_end], children)))
+}
+
+fn header_file(ebml: &[u8], mut children: Vec<u8>) -> Vec<u8> {
+    // An empty cluster, so the demuxer knows where the frames would start
+    children.extend_from_slice(&[0x1F, 0x43, 0xB6, 0x75, 0x80]);
+
+    let mut file = ebml.to_vec();
+    file.extend_from_slice(&SEGMENT.to_be_bytes());
+    // 8 byte size, the marker byte followed by 7 bytes of the size
+    file.push(0x01);
+    file.extend_from_slice(&(children.len() as u64).to_be_bytes()[1..]);
+    file.extend(children);
+
+    file
+}
+
+/// Reads the header of the element at `offset`, with the data offset relative to the file
+fn read_element(ranges: &RangeBuffer, offset: usize) -> Result<ElementHeader, RangeError> {
+    // 4 bytes id & 8 bytes size at most
+    let header = read_header(ranges.get(offset, 12)?, 0)?.ok_or("Could not read element header")?;
+
+    Ok(ElementHeader { data_offset: offset + header.data_offset, ..header })
+}
+
+/// Returns the ids & positions relative to the segment data of a SeekHead
+fn read_seek_head(data: &[u8]) -> Result<Vec<(u32, u64)>, String> {
+    let mut entries = Vec::new();
+    let mut position = 0;
+
+    while let Some(seek) = read_header(data, position)? {
+        let end = seek.end().ok_or("Seek has an unknown size")?;
+
+        if seek.id == SEEK {
+            let body = data.get(seek.data_offset..end).ok_or("Seek exceeds the SeekHead")?;
+            let (mut id, mut seek_position) = (None, None);
+            let mut child_position = 0;
+
+            while let Some(child) = read_header(body, child_position)? {
+                let child_end = child.end().ok_or("Seek child has an unknown size")?;
+                let value = body.get(child.data_offset..child_end).ok_or("Seek child exceeds the Seek")?;
+
+                match child.id {
+                    SEEK_ID => id = Some(read_uint(value) as u32),
+                    SEEK_POSITION => seek_position = Some(read_uint(value)),
+                    _ => {},
+                };
+
+                child_position = child_end;
+            }
+
+            if let (Some(id), Some(seek_position)) = (id, seek_position) {
+                entries.push((id, seek_position));
+            }
+        }
+
+        position = end;
+    }
+
+    Ok(entries)
+}
+
+/// Like [`header`], but reads the elements through `ranges` so the clusters are never fetched.
+/// Info & Tracks after the first cluster are found through the SeekHead. Also returns the position
+/// of the first cluster.
+pub fn find_header(ranges: &RangeBuffer) -> Result<(Vec<u8>, usize), RangeError> {
+    let ebml = read_element(ranges, 0)?;
+    if ebml.id != EBML {
+        return Err("Missing EBML header".into());
+    }
+    let ebml_end = ebml.end().ok_or("EBML header has an unknown size")?;
+    let segment = read_element(ranges, ebml_end)?;
+    if segment.id != SEGMENT {
+        return Err("Missing segment".into());
+    }
+
+    let mut children = Vec::new();
+    let mut found = Vec::new();
+    let mut seek_head = Vec::new();
+    let mut position = segment.data_offset;
+
+    let first_cluster = loop {
+        if position >= ranges.size() {
+            return Err("Could not find any cluster".into());
+        }
+
+        let header = read_element(ranges, position)?;
+        if header.id == CLUSTER {
+            break position;
+        }
+
+        let end = header.end().ok_or(format!("Element {:#X} has an unknown size", header.id))?;
+
+        match header.id {
+            SEEK_HEAD => seek_head = read_seek_head(ranges.get(header.data_offset, end - header.data_offset)?)?,
+            INFO | TRACKS => {
+                children.extend_from_slice(ranges.get(position, end - position)?);
+                found.push(header.id);
+            },
+            _ => {},
+        };
+
+        position = end;
+    };
+
+    for (id, seek_position) in seek_head {
+        if (id == INFO || id == TRACKS) && !found.contains(&id) {
+            let position = segment.data_offset + seek_position as usize;
+            let header = read_element(ranges, position)?;
+            let end = header.end().ok_or(format!("Element {:#X} has an unknown size", header.id))?;
+            children.extend_from_slice(ranges.get(position, end - position)?);
+            found.push(id);
+        }
+    }
+
+    Ok((header_file(ranges.get(0, ebml_end)?, children), first_cluster))
+}
+
+/// Returns where the cluster at `position` ends
+pub fn cluster_end(ranges: &RangeBuffer, position: usize) -> Result<usize, RangeError> {
+    let header = read_element(ranges, position)?;
+    if header.id != CLUSTER {
+        return Err(format!("Expected a cluster at {position}").into());
+    }
+
+    Ok(header.end().ok_or("Clusters of unknown size can't be read by range")?)
+}
+
+/// A (Simple)Block of the video track, `frames` are `(offset, size)` pairs into the file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Block {
+    pub track: u64,
+    /// Where the cluster the block is in starts
+    pub cluster: usize,
+    pub timestamp: i64,
+    pub duration: Option<u64>,
+    pub keyframe: bool,
+    pub frames: Vec<(usize, usize)>,
+}
+
+/// Walks all clusters of a Matroska file and returns the blocks in file order without copying
+/// any frame data.
+///
+/// The reader keeps its position, so when `data` only contains the start of a file `next` returns
+/// `None` at the first incomplete element and can be called again once more data is available.
+/// `data` doesn't have to start at the beginning of the file, `offset` is where it starts, which
+/// allows reading the clusters one by one.
+pub struct BlockReader {
+    position: usize,
+    cluster: usize,
+    cluster_timestamp: u64,
+}
+
+impl BlockReader {
+    pub fn new() -> Self {
+        Self { position: 0, cluster: 0, cluster_timestamp: 0 }
+    }
+
+    pub fn next(&mut self, data: &[u8], offset: usize) -> Result<Option<Block>, String> {
+        // Everything before `offset` has been skipped
+        self.position = self.position.max(offset);
+
+        loop {
+            let header = match read_header(data, self.position - offset)? {
+                Some(header) => header,
+                None => return Ok(None),
+            };
+
+            match header.id {
+                // Master elements are entered, which also makes clusters of unknown size work
+                SEGMENT | CLUSTER => {
+                    if header.id == CLUSTER {
+                        self.cluster = self.position;
+                    }
+                    self.position = offset + header.data_offset;
+                    continue;
+                },
+                _ => {},
+            };
+
+            let end = header.end().ok_or(format!("Element {:#X} has an unknown size", header.id))?;
+            let body = match data.get(header.data_offset..end) {
+                Some(body) => body,
+                None => return Ok(None),
+            };
+
+            self.position = offset + end;
+
+            match header.id {
+                TIMESTAMP => self.cluster_timestamp = read_uint(body),
+                SIMPLE_BLOCK => return Ok(Some(self.read_block(body, offset + header.data_offset, true)?)),
+                BLOCK_GROUP => {
+                    if let Some(block) = self.read_block_group(body, offset + header.data_offset)? {
+                        return Ok(Some(block));
+                    }
+                },
+                _ => {},
+            };
+        }
+    }
+
+    fn read_block_group(&self, data: &[u8], offset: usize) -> Result<Option<Block>, String> {
+        let mut position = 0;
+        let mut block = None;
+        let mut duration = None;
+        let mut referenced = false;
+
+        while let Some(header) = read_header(data, position)? {
+            let end = header.end().ok_or("Block group child has an unknown size")?;
+            let body = data.get(header.data_offset..end).ok_or("Block group child exceeds block group")?;
+
+            match header.id {
+                BLOCK => block = Some(self.read_block(body, offset + header.data_offset, false)?),
+                BLOCK_DURATION => duration = Some(read_uint(body)),
+                REFERENCE_BLOCK => referenced = true,
+                _ => {},
+            };
+
+            position = end;
+        }
+
+        Ok(block.map(|block| Block {
+            duration,
+            keyframe: !referenced,
+            ..block
+        }))
+    }
+
+    // https://www.matroska.org/technical/notes.html#block-structure
+    fn read_block(&self, data: &[u8], offset: usize, simple: bool) -> Result<Block, String> {
+        let (track, track_length) = read_vint(data, 0)?.ok_or("Could not read block track number")?;
+        let header = data.get(track_length..track_length + 3).ok_or("Could not read block header")?;
+        let relative_timestamp = i16::from_be_bytes([header[0], header[1]]);
+        let flags = header[2];
+        let keyframe = simple && flags & 0x80 != 0;
+
+        let mut position = track_length + 3;
+        let frames = match (flags & 0x06) >> 1 {
+            0 => vec![(offset + position, data.len() - position)],
+            lacing => {
+                let count = *data.get(position).ok_or("Could not read lace count")? as usize + 1;
+                position += 1;
+                let mut sizes = Vec::with_capacity(count);
+
+                match lacing {
+                    // Xiph lacing
+                    1 => {
+                        for _ in 0..count - 1 {
+                            let mut size = 0;
+                            loop {
+                                let byte = *data.get(position).ok_or("Could not read xiph lace size")?;
+                                position += 1;
+                                size += byte as usize;
+                                if byte != 255 {
+                                    break;
+                                }
+                            }
+                            sizes.push(size);
+                        }
+                    },
+                    // EBML lacing, the first size is stored as is and every next one as a signed difference
+                    3 => {
+                        let (first, length) = read_vint(data, position)?.ok_or("Could not read ebml lace size")?;
+                        position += length;
+                        let mut size = first as i64;
+                        sizes.push(size as usize);
+
+                        for _ in 1..count - 1 {
+                            let (raw, length) = read_vint(data, position)?.ok_or("Could not read ebml lace size")?;
+                            position += length;
+                            size += raw as i64 - ((1i64 << (7 * length - 1)) - 1);
+                            sizes.push(size as usize);
+                        }
+                    },
+                    // Fixed size lacing
+                    _ => {
+                        let size = (data.len() - position) / count;
+                        sizes.resize(count - 1, size);
+                    },
+                };
+
+                let laced: usize = sizes.iter().sum();
+                let last = (data.len() - position).checked_sub(laced).ok_or("Lace sizes exceed block size")?;
+                sizes.push(last);
+
+                sizes
+                    .into_iter()
+                    .scan(offset + position, |start, size| {
+                        let frame = (*start, size);
+                        *start += size;
+                        Some(frame)
+                    })
+                    .collect()
+            },
+        };
+
+        Ok(Block {
+            track,
+            cluster: self.cluster,
+            timestamp: self.cluster_timestamp as i64 + relative_timestamp as i64,
+            duration: None,
+            keyframe,
+            frames,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Block, BlockReader, projection_pose};
+
+    fn element(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut data = id.to_vec();
+        data.push(0x80 | body.len() as u8);
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn it_reads_simple_blocks_and_block_groups() {
+        let data: &[u8] = &[
+            0x1F, 0x43, 0xB6, 0x75, 0xFF, // Cluster of unknown size
+            0xE7, 0x81, 0x10, // Timestamp: 16
+            0xA3, 0x86, 0x81, 0x00, 0x00, 0x80, 0xAA, 0xBB, // SimpleBlock: track 1, keyframe
+            0xA0, 0x8D, // BlockGroup
+            0xA1, 0x85, 0x81, 0x00, 0x04, 0x00, 0xCC, // Block: track 1, +4
+            0xFB, 0x81, 0x7C, // ReferenceBlock: -4
+            0x9B, 0x81, 0x04, // BlockDuration: 4
+        ];
+
+        let mut reader = BlockReader::new();
+        assert_eq!(reader.next(data, 0), Ok(Some(Block { track: 1, cluster: 0, timestamp: 16, duration: None, keyframe: true, frames: vec![(14, 2)] })));
+        assert_eq!(reader.next(data, 0), Ok(Some(Block { track: 1, cluster: 0, timestamp: 20, duration: Some(4), keyframe: false, frames: vec![(24, 1)] })));
+        assert_eq!(reader.next(data, 0), Ok(None));
+    }
+
+    #[test]
+    fn it_waits_for_incomplete_elements() {
+        let data: &[u8] = &[
+            0x1F, 0x43, 0xB6, 0x75, 0xFF, // Cluster of unknown size
+            0xA3, 0x86, 0x81, 0x00, 0x00, 0x80, 0xAA, 0xBB, // SimpleBlock: track 1, keyframe
+        ];
+
+        let mut reader = BlockReader::new();
+        assert_eq!(reader.next(&data[..10], 0), Ok(None));
+        assert_eq!(reader.next(data, 0).unwrap().map(|block| block.frames), Some(vec![(11, 2)]));
+    }
+
+    #[test]
+    fn it_splits_laced_frames() {
+        let data: &[u8] = &[
+            0xA3, 0x8B, 0x81, 0x00, 0x00, 0x82, // SimpleBlock: track 1, keyframe, xiph lacing
+            0x02, 0x01, 0x02, // 3 frames, sizes 1, 2 and the rest
+            0xAA, 0xBB, 0xBB, 0xCC, // frame data
+        ];
+
+        let mut reader = BlockReader::new();
+        let block = reader.next(data, 0).unwrap().unwrap();
+        assert_eq!(block.frames, vec![(9, 1), (10, 2), (12, 1)]);
+    }
+
+    #[test]
+    fn it_reads_the_projection_pose_of_a_track() {
+        let projection = [
+            element(&[0x76, 0x71], &[0x00]), // ProjectionType: rectangular
+            element(&[0x76, 0x75], &(-90.0f32).to_be_bytes()), // ProjectionPoseRoll
+        ].concat();
+        let video = element(&[0xE0], &element(&[0x76, 0x70], &projection));
+        let tracks = [
+            element(&[0xAE], &element(&[0xD7], &[0x01])),
+            element(&[0xAE], &[element(&[0xD7], &[0x02]), video].concat()),
+        ].concat();
+        let data = [
+            element(&[0x1A, 0x45, 0xDF, 0xA3], &[]),
+            element(&[0x18, 0x53, 0x80, 0x67], &element(&[0x16, 0x54, 0xAE, 0x6B], &tracks)),
+        ].concat();
+
+        assert_eq!(projection_pose(&data, 2), Ok(Some((0.0, 0.0, -90.0))));
+        assert_eq!(projection_pose(&data, 1), Ok(None));
+    }
+}
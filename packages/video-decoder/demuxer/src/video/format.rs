@@ -0,0 +1,105 @@
+use std::fmt;
+
+/// The container (or lack of one) of a file, as recognized from its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Matroska & WebM
+    Matroska,
+    /// ISO base media file format: MP4, MOV, CMAF segments
+    IsoBmff,
+    Ivf,
+    MpegTs,
+    /// H.264 or HEVC elementary stream with start codes
+    AnnexB,
+    /// AV1 low overhead bitstream
+    Obu,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Matroska => "Matroska",
+            Format::IsoBmff => "ISO-BMFF",
+            Format::Ivf => "IVF",
+            Format::MpegTs => "MPEG-TS",
+            Format::AnnexB => "Annex B bitstream",
+            Format::Obu => "AV1 OBU bitstream",
+        })
+    }
+}
+
+const EBML_MAGIC: &[u8] = &[0x1A, 0x45, 0xDF, 0xA3];
+const IVF_MAGIC: &[u8] = b"DKIF";
+// Top level boxes a file or segment can start with
+const ISO_BMFF_BOXES: &[&[u8; 4]] = &[b"ftyp", b"styp", b"moov", b"moof"];
+const TS_PACKET_SIZE: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+// A temporal delimiter OBU with obu_has_size_field set and a size of 0
+const AV1_TEMPORAL_DELIMITER: &[u8] = &[0x12, 0x00];
+
+/// The number of bytes [`detect`] needs to recognize every format
+pub const SNIFF_SIZE: usize = TS_PACKET_SIZE * 2 + 1;
+
+pub fn detect(data: &[u8]) -> super::Result<Format> {
+    if data.starts_with(EBML_MAGIC) {
+        return Ok(Format::Matroska);
+    }
+
+    if data.starts_with(IVF_MAGIC) {
+        return Ok(Format::Ivf);
+    }
+
+    if let Some(name) = data.get(4..8) {
+        if ISO_BMFF_BOXES.iter().any(|box_name| &box_name[..] == name) {
+            return Ok(Format::IsoBmff);
+        }
+    }
+
+    // Every packet starts with a sync byte, checking the packets that are available avoids
+    // mistaking any file starting with 'G' for a transport stream
+    let packets: Vec<u8> = data.iter().step_by(TS_PACKET_SIZE).take(3).copied().collect();
+    if packets.len() > 1 && packets.iter().all(|byte| *byte == TS_SYNC_BYTE) {
+        return Ok(Format::MpegTs);
+    }
+
+    if data.starts_with(&[0, 0, 0, 1]) || data.starts_with(&[0, 0, 1]) {
+        return Ok(Format::AnnexB);
+    }
+
+    if data.starts_with(AV1_TEMPORAL_DELIMITER) {
+        return Ok(Format::Obu);
+    }
+
+    let start: Vec<String> = data.iter().take(8).map(|byte| format!("{byte:02X}")).collect();
+    Err(super::DemuxError::InvalidData(format!("Unknown container format, the data starts with [{}]", start.join(" "))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect, Format, TS_PACKET_SIZE};
+
+    #[test]
+    fn it_detects_containers_from_magic_bytes() {
+        let webm = std::fs::read("../../examples/src/assets/videos/video_2s_30fps_640x480_vp8.webm").unwrap();
+        let mp4 = std::fs::read("../../examples/src/assets/videos/video_2s_30fps_640x480_vp9.mp4").unwrap();
+        let mut ts = vec![0; TS_PACKET_SIZE * 2];
+        ts[0] = 0x47;
+        ts[TS_PACKET_SIZE] = 0x47;
+
+        assert_eq!(detect(&webm).unwrap(), Format::Matroska);
+        assert_eq!(detect(&mp4).unwrap(), Format::IsoBmff);
+        assert_eq!(detect(b"DKIF\0\0\x20\0VP90").unwrap(), Format::Ivf);
+        assert_eq!(detect(&ts).unwrap(), Format::MpegTs);
+        assert_eq!(detect(&[0, 0, 0, 1, 0x67, 0x64]).unwrap(), Format::AnnexB);
+        assert_eq!(detect(&[0x12, 0x00, 0x0A, 0x0B]).unwrap(), Format::Obu);
+    }
+
+    #[test]
+    fn it_rejects_unknown_data() {
+        assert_eq!(
+            detect(b"GIF89a").unwrap_err().to_string(),
+            "Unknown container format, the data starts with [47 49 46 38 39 61]",
+        );
+        assert!(detect(&[]).is_err());
+    }
+}
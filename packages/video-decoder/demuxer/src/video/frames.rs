@@ -1,13 +1,44 @@
-use std::ops::Deref;
-use js_sys::Uint8Array;
-use wasm_bindgen::JsValue;
-use web_sys::{EncodedVideoChunkType, EncodedVideoChunkInit, EncodedVideoChunk};
+use super::timeline::Presentation;
 
+/// A frame in the file, `offset` and `size` point into the buffer the file was loaded from.
+/// `timestamp` is when the frame is presented, which isn't in decode order when there are B-frames.
+/// Decode-only frames are outside of the presentation timeline, other frames depend on them.
 pub struct FrameCache {
     pub keyframe: bool,
-    pub timestamp: u64,
+    pub timestamp: i64,
+    /// The same as `timestamp` unless the container stores decode times
+    pub decode_timestamp: i64,
+    pub duration: Option<u64>,
+    pub offset: usize,
     pub size: u32,
-    pub chunk: EncodedVideoChunk,
+    pub decode_only: bool,
+}
+
+/// Which frame to go to for a timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekMode {
+    /// The frame with the closest timestamp
+    Nearest,
+    /// The last keyframe at or before the timestamp, which decodes the fastest
+    PreviousKeyframe,
+    /// The first keyframe at or after the timestamp
+    NextKeyframe,
+    /// The keyframe with the closest timestamp
+    NearestKeyframe,
+}
+
+/// A row of the frame table, for showing the structure of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// Presentation index
+    pub index: usize,
+    pub decode_index: usize,
+    pub decode_timestamp: i64,
+    pub timestamp: i64,
+    pub duration: Option<u64>,
+    pub size: u32,
+    pub keyframe: bool,
+    pub offset: usize,
 }
 
 pub enum FrameCacheError {
@@ -15,82 +46,448 @@ pub enum FrameCacheError {
     NoFrames(String)
 }
 
-impl From<JsValue> for FrameCacheError {
-    fn from(value: JsValue) -> Self {
-        match value.as_string() {
-            Some(err) => FrameCacheError::Init(err),
-            None => FrameCacheError::Init(format!("{value:?}"))
-        }
-    }
-}
-
 impl FrameCache {
-    pub fn init(bytes: &[u8], ts: f64, keyframe: bool) -> Result<Self, FrameCacheError> {
-        let chunk_type = match keyframe {
-            true => EncodedVideoChunkType::Key,
-            false => EncodedVideoChunkType::Delta,
-        };
-
-        let size = bytes.len() as u32;
-        let data = Uint8Array::new_with_length(size);
+    pub fn new(offset: usize, size: u32, timestamp: i64, duration: Option<u64>, keyframe: bool) -> Self {
+        Self { keyframe, timestamp, decode_timestamp: timestamp, duration, offset, size, decode_only: false }
+    }
 
-        data.copy_from(&bytes);
-        let obj = data.deref();
-        let init = EncodedVideoChunkInit::new(obj, ts as f64, chunk_type);
-        let chunk = EncodedVideoChunk::new(&init)?;
+    /// Places the frame on the presentation timeline
+    pub fn present(offset: usize, size: u32, presentation: Presentation, keyframe: bool) -> Self {
+        Self {
+            decode_only: presentation.decode_only,
+            ..Self::new(offset, size, presentation.timestamp, Some(presentation.duration), keyframe)
+        }
+    }
 
-        Ok(Self { keyframe, timestamp: ts as u64, size, chunk })
+    pub fn data<'a>(&self, buffer: &'a [u8]) -> Option<&'a [u8]> {
+        buffer.get(self.offset..self.offset + self.size as usize)
     }
 }
 
+/// The frames of the video track in decode order. `count` is the number of leading frames whose
+/// data has been loaded, frames after it are known but can't be decoded yet.
+///
+/// The loaded frames that aren't decode-only are also indexed in presentation order, frame indices
+/// are in decode order unless they are called presentation indices.
+#[derive(Default)]
 pub struct FrameCacheStore {
     store: Vec<FrameCache>,
     count: usize,
+    // The decode indices of the loaded frames, sorted by timestamp
+    presentation: Vec<usize>,
+    // The decode indices of the loaded keyframes, each starts a group of pictures
+    keyframes: Vec<usize>,
+    // Whether the duration of a loaded frame is derived from the next one in presentation order
+    derived: Vec<bool>,
 }
 
 impl FrameCacheStore {
     pub fn new(store: Vec<FrameCache>) -> Result<Self, FrameCacheError> {
-        if store.len() < 1 {
+        if store.is_empty() {
             return Err(FrameCacheError::NoFrames("Cannot initialize frame store cache with 0 frames".to_string()))
         }
 
-        let count = store.len();
-        Ok(Self { store, count })
+        let mut store = Self { store, count: 0, presentation: Vec::new(), keyframes: Vec::new(), derived: Vec::new() };
+        store.set_loaded(usize::MAX);
+        Ok(store)
+    }
+
+    /// Adds the next frame in decode order
+    pub fn push(&mut self, frame: FrameCache) {
+        self.store.push(frame);
+    }
+
+    /// Marks the frames that are within the first `size` bytes of the file as loaded. A frame whose
+    /// duration is unknown lasts until the next frame in presentation order.
+    pub fn set_loaded(&mut self, size: usize) {
+        while let Some(frame) = self.store.get(self.count) {
+            if frame.offset + frame.size as usize > size {
+                break;
+            }
+
+            let (timestamp, decode_only, keyframe) = (frame.timestamp, frame.decode_only, frame.keyframe);
+            self.derived.push(frame.duration.is_none());
+
+            // Frames mostly arrive in presentation order, so this inserts near the end
+            if !decode_only {
+                let key = (timestamp, self.count);
+                let position = self.presentation.partition_point(|idx| (self.store[*idx].timestamp, *idx) < key);
+                self.presentation.insert(position, self.count);
+
+                self.derive_duration(position);
+                if let Some(previous) = position.checked_sub(1) {
+                    self.derive_duration(previous);
+                }
+            }
+            if keyframe {
+                self.keyframes.push(self.count);
+            }
+            self.count += 1;
+        }
+    }
+
+    /// Lets the frame presented at `position` last until the next one, unless the container knows
+    /// its duration
+    fn derive_duration(&mut self, position: usize) {
+        let idx = self.presentation[position];
+        if !self.derived[idx] {
+            return;
+        }
+
+        let next = self.presentation.get(position + 1).map(|next| self.store[*next].timestamp);
+        let frame = &mut self.store[idx];
+        frame.duration = next.map(|next| next - frame.timestamp).filter(|duration| *duration > 0).map(|duration| duration as u64);
     }
 
     pub fn total_size(&self) -> u64 {
-        self.store.iter().fold(0, |acc, el| acc + (el.size as u64))
+        self.loaded().iter().fold(0, |acc, el| acc + (el.size as u64))
     }
 
     pub fn count(&self) -> usize {
         self.count
     }
 
+    /// The number of loaded frames that are presented
+    pub fn presentation_count(&self) -> usize {
+        self.presentation.len()
+    }
+
     pub fn get(&self, id: usize) -> Option<&FrameCache> {
-        self.store.get(id)
+        self.loaded().get(id)
     }
 
-    pub fn timestamp_to_frame(&self, timestamp: u64) -> Option<usize> {
-        for (idx, frame) in self.store.iter().enumerate() {
-            if frame.timestamp == timestamp {
-                return Some(idx);
-            }
+    /// The duration of a frame in microseconds
+    pub fn duration(&self, id: usize) -> Option<u64> {
+        self.get(id).and_then(|frame| frame.duration)
+    }
+
+    fn loaded(&self) -> &[FrameCache] {
+        &self.store[..self.count]
+    }
+
+    /// The presentation index of the frame with the given timestamp
+    pub fn timestamp_to_frame(&self, timestamp: i64) -> Option<usize> {
+        let position = self.presentation.partition_point(|idx| self.store[*idx].timestamp < timestamp);
+
+        self.presentation
+            .get(position)
+            .filter(|idx| self.store[**idx].timestamp == timestamp)
+            .map(|_| position)
+    }
+
+    /// The presentation index of the frame that is shown at `timestamp`, the first frame for
+    /// timestamps before it
+    pub fn frame_at(&self, timestamp: i64) -> Option<usize> {
+        let position = self.presentation.partition_point(|idx| self.store[*idx].timestamp <= timestamp);
+
+        match position {
+            0 if self.presentation.is_empty() => None,
+            0 => Some(0),
+            position => Some(position - 1),
+        }
+    }
+
+    /// The presentation index of the frame to go to for `timestamp`
+    pub fn seek_frame(&self, timestamp: i64, mode: SeekMode) -> Option<usize> {
+        let keyframe = |idx: &usize| self.store[self.presentation[*idx]].keyframe;
+
+        match mode {
+            SeekMode::Nearest => {
+                let next = self.presentation.partition_point(|idx| self.store[*idx].timestamp < timestamp);
+                let distance = |idx: usize| (self.store[self.presentation[idx]].timestamp - timestamp).abs();
+
+                match (next.checked_sub(1), Some(next).filter(|next| *next < self.presentation.len())) {
+                    (Some(previous), Some(next)) if distance(next) < distance(previous) => Some(next),
+                    (Some(previous), _) => Some(previous),
+                    (None, next) => next,
+                }
+            },
+            SeekMode::PreviousKeyframe => (0..=self.frame_at(timestamp)?).rev().find(keyframe),
+            SeekMode::NextKeyframe => {
+                let next = self.presentation.partition_point(|idx| self.store[*idx].timestamp < timestamp);
+                (next..self.presentation.len()).find(keyframe)
+            },
+            SeekMode::NearestKeyframe => {
+                let distance = |idx: &usize| (self.store[self.presentation[*idx]].timestamp - timestamp).abs();
+                let previous = self.seek_frame(timestamp, SeekMode::PreviousKeyframe);
+                let next = self.seek_frame(timestamp, SeekMode::NextKeyframe);
+
+                previous.into_iter().chain(next).min_by_key(distance)
+            },
+        }
+    }
+
+    /// The presentation indices of the keyframes presented as `from` up to and including `to`
+    pub fn keyframes(&self, from: usize, to: usize) -> Vec<usize> {
+        let to = to.min(self.presentation.len().saturating_sub(1));
+
+        (from..=to)
+            .filter(|idx| self.presentation.get(*idx).is_some_and(|frame| self.store[*frame].keyframe))
+            .collect()
+    }
+
+    /// The presentation indices of the keyframes nearest to every `stride`th frame presented as
+    /// `from` up to and including `to`, without duplicates. `to` is clamped to the last presented
+    /// frame.
+    pub fn nearest_keyframes(&self, from: usize, to: usize, stride: usize) -> Vec<usize> {
+        let to = match self.presentation.len().checked_sub(1) {
+            Some(last) => to.min(last),
+            None => return Vec::new(),
+        };
+        if from > to {
+            return Vec::new();
         }
 
-        None
+        let mut keyframes: Vec<usize> = (from..=to)
+            .step_by(stride.max(1))
+            .filter_map(|idx| self.seek_frame(self.store[self.presentation[idx]].timestamp, SeekMode::NearestKeyframe))
+            .collect();
+        keyframes.dedup();
+
+        keyframes
     }
 
+    /// The loaded frames in presentation order, decode-only frames aren't presented
+    pub fn table(&self) -> Vec<FrameInfo> {
+        self.presentation
+            .iter()
+            .enumerate()
+            .map(|(index, decode_index)| {
+                let frame = &self.store[*decode_index];
+                FrameInfo {
+                    index,
+                    decode_index: *decode_index,
+                    decode_timestamp: frame.decode_timestamp,
+                    timestamp: frame.timestamp,
+                    duration: frame.duration,
+                    size: frame.size,
+                    keyframe: frame.keyframe,
+                    offset: frame.offset,
+                }
+            })
+            .collect()
+    }
+
+    /// The number of groups of pictures that have been loaded
+    pub fn gop_count(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    /// The group of pictures the frame presented as `idx` is decoded in, frames before the first
+    /// keyframe aren't in one
+    pub fn gop_of(&self, idx: usize) -> Option<usize> {
+        let frame = self.presentation_to_decode(idx)?;
+
+        self.keyframes.partition_point(|keyframe| *keyframe <= frame).checked_sub(1)
+    }
+
+    /// The first and last decode index of a group of pictures, decoding these outputs all its frames
+    pub fn gop_decode_range(&self, gop: usize) -> Option<(usize, usize)> {
+        let start = *self.keyframes.get(gop)?;
+        let end = self.keyframes.get(gop + 1).copied().unwrap_or(self.count);
+
+        Some((start, end - 1))
+    }
+
+    /// The presentation indices of the frames in a group of pictures, in presentation order
+    pub fn gop_frames(&self, gop: usize) -> Vec<usize> {
+        let (start, end) = match self.gop_decode_range(gop) {
+            Some(range) => range,
+            None => return Vec::new(),
+        };
+        let mut frames: Vec<usize> = (start..=end).filter_map(|idx| self.decode_to_presentation(idx)).collect();
+        frames.sort_unstable();
+
+        frames
+    }
+
+    /// The decode index of the frame that is presented as frame `idx`
+    pub fn presentation_to_decode(&self, idx: usize) -> Option<usize> {
+        self.presentation.get(idx).copied()
+    }
+
+    /// The presentation index of the frame that is decoded as frame `idx`
+    pub fn decode_to_presentation(&self, idx: usize) -> Option<usize> {
+        let frame = self.get(idx)?;
+
+        self.presentation
+            .binary_search_by_key(&(frame.timestamp, idx), |idx| (self.store[*idx].timestamp, *idx))
+            .ok()
+    }
+
+    /// The first and last decode index of the frames presented as `from` up to and including `to`.
+    /// Decoding everything in between (from the keyframe before it) outputs all of these frames.
+    pub fn decode_range(&self, from: usize, to: usize) -> Option<(usize, usize)> {
+        let frames = self.presentation.get(from..=to.min(self.presentation.len().saturating_sub(1)))?;
+
+        Some((*frames.iter().min()?, *frames.iter().max()?))
+    }
+
+    /// The decode index of the last loaded keyframe up to and including the frame decoded as
+    /// `before`
     pub fn find_prev_key_frame_before(&self, before: usize) -> Option<usize> {
-        for (idx, f) in self.store.iter().enumerate().rev() {
-            if idx > before {
-                continue;
-            }
+        let position = self.keyframes.partition_point(|keyframe| *keyframe <= before);
 
-            if f.keyframe {
-                return Some(idx);
-            }
+        position.checked_sub(1).map(|position| self.keyframes[position])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameCache, FrameCacheStore, FrameInfo, SeekMode};
+    use crate::video::timeline::Presentation;
+
+    #[test]
+    fn it_only_counts_loaded_frames() {
+        let mut store = FrameCacheStore::default();
+        store.push(FrameCache::new(10, 5, 0, None, true));
+        store.push(FrameCache::new(15, 5, 1, None, false));
+        store.push(FrameCache::new(20, 5, 2, None, false));
+
+        assert_eq!(store.count(), 0);
+        store.set_loaded(22);
+        assert_eq!(store.count(), 2);
+        assert!(store.get(2).is_none());
+        store.set_loaded(25);
+        assert_eq!(store.count(), 3);
+        assert_eq!(store.timestamp_to_frame(2), Some(2));
+    }
+
+    #[test]
+    fn it_derives_missing_durations_from_the_next_frame() {
+        let mut store = FrameCacheStore::default();
+        store.push(FrameCache::new(0, 5, 0, None, true));
+        store.push(FrameCache::new(5, 5, 40, Some(20), false));
+        store.push(FrameCache::new(10, 5, 60, None, false));
+        store.set_loaded(15);
+
+        assert_eq!(store.duration(0), Some(40));
+        assert_eq!(store.duration(1), Some(20));
+        assert_eq!(store.duration(2), None);
+    }
+
+    #[test]
+    fn it_derives_missing_durations_in_presentation_order() {
+        // I P B B, presented as I B B P
+        let mut store = FrameCacheStore::default();
+        store.push(FrameCache::new(0, 5, 0, None, true));
+        store.push(FrameCache::new(5, 5, 30, None, false));
+        store.set_loaded(10);
+        assert_eq!(store.duration(0), Some(30));
+
+        store.push(FrameCache::new(10, 5, 10, None, false));
+        store.push(FrameCache::new(15, 5, 20, None, false));
+        store.set_loaded(20);
+
+        assert_eq!(store.duration(0), Some(10));
+        assert_eq!(store.duration(2), Some(10));
+        assert_eq!(store.duration(3), Some(10));
+        assert_eq!(store.duration(1), None);
+    }
+
+    /// I P B B, presented as I B B P, with decode timestamps in decode order
+    fn reordered_store() -> FrameCacheStore {
+        let mut store = FrameCacheStore::default();
+        for (idx, timestamp) in [0, 30, 10, 20].iter().enumerate() {
+            let mut frame = FrameCache::new(idx * 5, 5, *timestamp, Some(10), idx == 0);
+            frame.decode_timestamp = idx as i64 * 10 - 10;
+            store.push(frame);
         }
+        store.set_loaded(20);
+        store
+    }
+
+    #[test]
+    fn it_maps_decode_order_to_presentation_order() {
+        let store = reordered_store();
+
+        assert_eq!(store.presentation_to_decode(3), Some(1));
+        assert_eq!(store.decode_to_presentation(2), Some(1));
+        assert_eq!(store.timestamp_to_frame(30), Some(3));
+        assert_eq!(store.timestamp_to_frame(25), None);
+        // The first B-frame is decoded after the P-frame it references
+        assert_eq!(store.decode_range(1, 1), Some((2, 2)));
+        assert_eq!(store.decode_range(0, 1), Some((0, 2)));
+        assert_eq!(store.decode_range(2, 3), Some((1, 3)));
+    }
+
+    #[test]
+    fn it_lists_the_frames_in_presentation_order() {
+        let table = reordered_store().table();
+        let rows: Vec<(usize, usize, i64, i64)> = table
+            .iter()
+            .map(|frame| (frame.index, frame.decode_index, frame.decode_timestamp, frame.timestamp))
+            .collect();
 
-        None
+        assert_eq!(rows, vec![(0, 0, -10, 0), (1, 2, 10, 10), (2, 3, 20, 20), (3, 1, 0, 30)]);
+        assert!(table[0].keyframe && !table[3].keyframe);
+        assert_eq!(table[3].offset, 5);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn it_finds_frames_by_timestamp() {
+        let mut store = FrameCacheStore::default();
+        for idx in 0..6 {
+            store.push(FrameCache::new(idx * 5, 5, idx as i64 * 40, Some(40), idx % 3 == 0));
+        }
+        store.set_loaded(30);
+
+        assert_eq!(store.frame_at(-10), Some(0));
+        assert_eq!(store.frame_at(79), Some(1));
+        assert_eq!(store.frame_at(80), Some(2));
+        assert_eq!(store.seek_frame(59, SeekMode::Nearest), Some(1));
+        assert_eq!(store.seek_frame(61, SeekMode::Nearest), Some(2));
+        assert_eq!(store.seek_frame(1000, SeekMode::Nearest), Some(5));
+        assert_eq!(store.seek_frame(110, SeekMode::PreviousKeyframe), Some(0));
+        assert_eq!(store.seek_frame(120, SeekMode::PreviousKeyframe), Some(3));
+        assert_eq!(store.seek_frame(41, SeekMode::NextKeyframe), Some(3));
+        assert_eq!(store.seek_frame(121, SeekMode::NextKeyframe), None);
+        assert_eq!(store.seek_frame(50, SeekMode::NearestKeyframe), Some(0));
+        assert_eq!(store.seek_frame(70, SeekMode::NearestKeyframe), Some(3));
+        assert_eq!(store.keyframes(0, 10), vec![0, 3]);
+        assert!(store.keyframes(1, 2).is_empty());
+        assert!(FrameCacheStore::default().keyframes(0, 0).is_empty());
+        assert_eq!(store.nearest_keyframes(0, 100, 1), vec![0, 3]);
+        assert_eq!(store.nearest_keyframes(4, 100, 0), vec![3]);
+        assert!(store.nearest_keyframes(6, 100, 1).is_empty());
+        assert!(FrameCacheStore::default().nearest_keyframes(0, 0, 1).is_empty());
+    }
+
+    #[test]
+    fn it_groups_frames_by_keyframe() {
+        // I P B | I P B, presented as I B P | I B P
+        let mut store = FrameCacheStore::default();
+        for (idx, timestamp) in [0, 20, 10, 30, 50, 40].iter().enumerate() {
+            store.push(FrameCache::new(idx * 5, 5, *timestamp, Some(10), idx % 3 == 0));
+        }
+        store.set_loaded(30);
+
+        assert_eq!(store.gop_count(), 2);
+        assert_eq!(store.gop_of(2), Some(0));
+        assert_eq!(store.gop_of(3), Some(1));
+        assert_eq!(store.gop_decode_range(1), Some((3, 5)));
+        assert_eq!(store.gop_frames(1), vec![3, 4, 5]);
+        assert_eq!(store.gop_decode_range(2), None);
+        assert_eq!(store.find_prev_key_frame_before(2), Some(0));
+        assert_eq!(store.find_prev_key_frame_before(3), Some(3));
+        assert_eq!(store.find_prev_key_frame_before(100), Some(3));
+
+        let table = store.table();
+        assert_eq!(table.len(), 6);
+        assert_eq!(table[1], FrameInfo { index: 1, decode_index: 2, decode_timestamp: 10, timestamp: 10, duration: Some(10), size: 5, keyframe: false, offset: 10 });
+    }
+
+    #[test]
+    fn it_leaves_decode_only_frames_out_of_the_presentation() {
+        let mut store = FrameCacheStore::default();
+        store.push(FrameCache::present(0, 5, Presentation { timestamp: -10, duration: 10, decode_only: true }, true));
+        store.push(FrameCache::present(5, 5, Presentation { timestamp: 0, duration: 10, decode_only: false }, false));
+        store.set_loaded(10);
+
+        assert_eq!(store.count(), 2);
+        assert_eq!(store.presentation_count(), 1);
+        assert_eq!(store.presentation_to_decode(0), Some(1));
+        assert_eq!(store.timestamp_to_frame(-10), None);
+        assert_eq!(store.decode_range(0, 0), Some((1, 1)));
+    }
+}
@@ -0,0 +1,102 @@
+use super::CodecPrivate;
+
+/// The profile, tier & level part of an `HEVCDecoderConfigurationRecord` (hvcC)
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hevc {
+    pub format: String,
+    pub profile_space: u8,
+    pub tier: bool,
+    pub profile_idc: u8,
+    pub profile_compatibility: u32,
+    pub constraint_indicator: [u8; 6],
+    pub level_idc: u8,
+}
+
+impl Hevc {
+    // ISO/IEC 14496-15 8.3.3.1
+    pub fn from(format: &str, hvcc: &[u8]) -> Result<Self, String> {
+        if hvcc.len() < 13 {
+            return Err("hvcC is too short".to_string());
+        }
+
+        if hvcc[0] != 1 {
+            return Err(format!("Unsupported hvcC version {}", hvcc[0]));
+        }
+
+        let mut constraint_indicator = [0; 6];
+        constraint_indicator.copy_from_slice(&hvcc[6..12]);
+
+        Ok(Self {
+            format: format.to_string(),
+            profile_space: hvcc[1] >> 6,
+            tier: hvcc[1] & 0x20 != 0,
+            profile_idc: hvcc[1] & 0x1F,
+            profile_compatibility: u32::from_be_bytes([hvcc[2], hvcc[3], hvcc[4], hvcc[5]]),
+            constraint_indicator,
+            level_idc: hvcc[12],
+        })
+    }
+
+    fn profile_space(&self) -> &str {
+        match self.profile_space {
+            1 => "A",
+            2 => "B",
+            3 => "C",
+            _ => "",
+        }
+    }
+
+    fn tier(&self) -> &str {
+        match self.tier {
+            true => "H",
+            false => "L",
+        }
+    }
+}
+
+impl CodecPrivate for Hevc {
+    // ISO/IEC 14496-15 E.3
+    fn to_codec_string(&self) -> String {
+        // cccc.PP.CC.TLL.BB
+        let mut codec = format!(
+            "{}.{}{}.{:X}.{}{}",
+            self.format,
+            self.profile_space(),
+            self.profile_idc,
+            self.profile_compatibility.reverse_bits(),
+            self.tier(),
+            self.level_idc,
+        );
+
+        // Trailing bytes of the constraint flags that are zero are omitted
+        let length = self.constraint_indicator.iter().rposition(|byte| *byte != 0).map_or(0, |idx| idx + 1);
+        for byte in &self.constraint_indicator[..length] {
+            codec.push_str(&format!(".{byte:X}"));
+        }
+
+        codec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::video::{hevc::Hevc, CodecPrivate};
+
+    #[test]
+    fn it_builds_the_codec_string_from_hvcc() {
+        let main: &[u8] = &[
+            0x01, 0x01, 0x60, 0x00, 0x00, 0x00, // version, Main profile, compatibility flags
+            0xB0, 0x00, 0x00, 0x00, 0x00, 0x00, // constraint flags
+            0x5D, // level 3.1
+        ];
+        let main_10_high_tier: &[u8] = &[
+            0x01, 0x22, 0x20, 0x00, 0x00, 0x00, // version, high tier Main 10 profile, compatibility flags
+            0x90, 0x00, 0x00, 0x00, 0x00, 0x00, // constraint flags
+            0x99, // level 5.1
+        ];
+
+        assert_eq!(Hevc::from("hvc1", main).unwrap().to_codec_string(), "hvc1.1.6.L93.B0");
+        assert_eq!(Hevc::from("hev1", main_10_high_tier).unwrap().to_codec_string(), "hev1.2.4.H153.90");
+        assert_eq!(Hevc::from("hvc1", &main[..12]), Err("hvcC is too short".to_string()));
+    }
+}
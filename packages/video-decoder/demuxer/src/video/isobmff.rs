@@ -0,0 +1,494 @@
+// Minimal ISO-BMFF box walking for the parts the mp4 crate does not expose, like the raw codec
+// configuration boxes of a sample entry.
+
+use std::convert::{TryFrom, TryInto};
+
+use super::range::{RangeBuffer, RangeError};
+
+pub type FourCC = [u8; 4];
+
+/// Iterates over the boxes in `data`, stops at the first box that is truncated.
+pub struct Boxes<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Boxes<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    /// The offset right after the last returned box
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+/// Reads the name, header size and box size from the start of a box, the size is `None` when the
+/// box extends to the end of the file. `Ok(None)` when the header is incomplete.
+fn read_box_header(data: &[u8]) -> Result<Option<(FourCC, usize, Option<usize>)>, String> {
+    let header = match data.get(..8) {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let name = [header[4], header[5], header[6], header[7]];
+
+    let (header_size, size) = match size {
+        0 => return Ok(Some((name, 8, None))),
+        // The size is stored as a 64 bit integer after the name
+        1 => match data.get(8..16) {
+            Some(large) => {
+                let size = u64::from_be_bytes(large.try_into().unwrap());
+                (16, usize::try_from(size).map_err(|_| format!("Box {} of {} bytes doesn't fit in memory", String::from_utf8_lossy(&name), size))?)
+            },
+            None => return Ok(None),
+        },
+        size => (8, size),
+    };
+
+    // A box can't be smaller than its header, walking past it would never advance
+    if size < header_size {
+        return Err(format!("Box {} of {} bytes is smaller than its header", String::from_utf8_lossy(&name), size));
+    }
+
+    Ok(Some((name, header_size, Some(size))))
+}
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = (FourCC, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (name, header_size, size) = read_box_header(self.data.get(self.offset..)?).ok()??;
+        let start = self.offset + header_size;
+        let end = match size {
+            Some(size) => self.offset.checked_add(size)?,
+            None => self.data.len(),
+        };
+
+        let body = self.data.get(start..end)?;
+        self.offset = end;
+
+        Some((name, body))
+    }
+}
+
+/// Copies the `ftyp` & `moov` boxes into a file of their own once the `moov` box is complete, or
+/// returns `None` while it isn't. The chunk offsets in it still point into the complete file.
+pub fn header(data: &[u8]) -> Option<Vec<u8>> {
+    let mut header = Vec::new();
+    let mut boxes = Boxes::new(data);
+    let mut start = 0;
+
+    while let Some((name, _)) = boxes.next() {
+        let end = boxes.offset();
+
+        match &name {
+            b"ftyp" => header.extend_from_slice(&data[start..end]),
+            b"moov" => {
+                header.extend_from_slice(&data[start..end]);
+                return Some(header);
+            },
+            _ => {},
+        };
+
+        start = end;
+    }
+
+    None
+}
+
+/// Like [`header`], but reads the boxes through `ranges` so the data in between is never fetched.
+/// Also returns where the `moov` box ends, the fragments of a fragmented file follow it.
+pub fn find_header(ranges: &RangeBuffer) -> Result<(Vec<u8>, usize), RangeError> {
+    let mut header = Vec::new();
+    let mut offset = 0;
+
+    while offset < ranges.size() {
+        let (name, end) = box_at(ranges, offset)?;
+
+        match &name {
+            b"ftyp" => header.extend_from_slice(ranges.get(offset, end - offset)?),
+            b"moov" => {
+                header.extend_from_slice(ranges.get(offset, end - offset)?);
+                return Ok((header, end));
+            },
+            _ => {},
+        };
+
+        offset = end;
+    }
+
+    Err("Could not find the moov box".into())
+}
+
+/// Returns the name of the box at `offset` and where it ends, an error when the box doesn't end
+/// after `offset` so walking the boxes always advances
+pub fn box_at(ranges: &RangeBuffer, offset: usize) -> Result<(FourCC, usize), RangeError> {
+    let (name, _, size) = read_box_header(ranges.get(offset, 16)?)?.ok_or("Could not read box header")?;
+    let end = offset.checked_add(size.unwrap_or(ranges.size().saturating_sub(offset)));
+
+    match end.filter(|end| *end > offset) {
+        Some(end) => Ok((name, end)),
+        None => Err(format!("Box {} at {} has an invalid size", String::from_utf8_lossy(&name), offset).into()),
+    }
+}
+
+/// Walks the top level boxes of a file and returns the `moof` boxes, resumable like the Matroska
+/// block reader: `data` is the part of the file starting at `offset`.
+pub struct FragmentReader {
+    position: usize,
+}
+
+impl FragmentReader {
+    pub fn new() -> Self {
+        Self { position: 0 }
+    }
+
+    /// Returns the offset & body of the next complete `moof` box
+    pub fn next<'a>(&mut self, data: &'a [u8], offset: usize) -> Result<Option<(usize, &'a [u8])>, String> {
+        self.position = self.position.max(offset);
+
+        loop {
+            let (name, header_size, size) = match data.get(self.position - offset..).map(read_box_header).transpose()?.flatten() {
+                // A box extending to the end of the file can only be the last mdat
+                Some((name, header_size, Some(size))) => (name, header_size, size),
+                _ => return Ok(None),
+            };
+            let position = self.position;
+            let end = position.checked_add(size).ok_or(format!("Box at {} of {} bytes runs past the end of memory", position, size))?;
+
+            if &name == b"moof" {
+                let body = match data.get(position - offset + header_size..end - offset) {
+                    Some(body) => body,
+                    None => return Ok(None),
+                };
+                self.position = end;
+                return Ok(Some((position, body)));
+            }
+
+            self.position = end;
+        }
+    }
+}
+
+pub fn find<'a>(data: &'a [u8], name: &FourCC) -> Option<&'a [u8]> {
+    Boxes::new(data).find(|(found, _)| found == name).map(|(_, body)| body)
+}
+
+pub fn find_path<'a>(data: &'a [u8], path: &[&FourCC]) -> Option<&'a [u8]> {
+    path.iter().try_fold(data, |data, name| find(data, name))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Returns the track id from the body of a `tkhd` box
+fn tkhd_track_id(tkhd: &[u8]) -> Option<u32> {
+    match tkhd.first()? {
+        1 => read_u32(tkhd, 4 + 8 + 8),
+        _ => read_u32(tkhd, 4 + 4 + 4),
+    }
+}
+
+/// Returns the body of the `trak` box with the given track id from the body of a `moov` box
+pub fn find_trak(moov: &[u8], track_id: u32) -> Option<&[u8]> {
+    Boxes::new(moov)
+        .filter(|(name, _)| name == b"trak")
+        .map(|(_, trak)| trak)
+        .find(|trak| find(trak, b"tkhd").and_then(tkhd_track_id) == Some(track_id))
+}
+
+/// Sample defaults of a track from its `trex` box, used by fragments that don't override them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SampleDefaults {
+    pub duration: u32,
+    pub size: u32,
+    pub flags: u32,
+}
+
+impl SampleDefaults {
+    /// Reads the defaults of every track from a file or header, together with their track id
+    pub fn read(file: &[u8]) -> Vec<(u32, Self)> {
+        let mvex = match find_path(file, &[b"moov", b"mvex"]) {
+            Some(mvex) => mvex,
+            None => return Vec::new(),
+        };
+
+        // version & flags, track_ID, default_sample_description_index, duration, size, flags
+        Boxes::new(mvex)
+            .filter(|(name, _)| name == b"trex")
+            .filter_map(|(_, trex)| Some((read_u32(trex, 4)?, Self {
+                duration: read_u32(trex, 12)?,
+                size: read_u32(trex, 16)?,
+                flags: read_u32(trex, 20)?,
+            })))
+            .collect()
+    }
+
+    /// The defaults of the track with the given id
+    pub fn of(defaults: &[(u32, Self)], track_id: u32) -> Self {
+        defaults.iter().find(|(id, _)| *id == track_id).map(|(_, defaults)| *defaults).unwrap_or_default()
+    }
+}
+
+/// A sample of a track fragment, `offset` is the position of its data in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FragmentSample {
+    pub offset: usize,
+    pub size: u32,
+    pub decode_time: u64,
+    pub duration: u32,
+    /// Presentation time minus decode time
+    pub composition_offset: i32,
+    pub keyframe: bool,
+}
+
+const SAMPLE_IS_NON_SYNC: u32 = 0x10000;
+
+/// Reads the samples of a track from the body of a `moof` box that starts at `moof_offset`.
+/// `decode_time` is used when a fragment has no `tfdt` box, the samples are returned together with
+/// the decode time following them.
+pub fn read_fragment(moof: &[u8], moof_offset: usize, track_id: u32, defaults: &[(u32, SampleDefaults)], mut decode_time: u64) -> Result<(Vec<FragmentSample>, u64), String> {
+    let mut samples = Vec::new();
+    // Without an explicit base offset the data of a track fragment follows that of the previous
+    // one, the first starts at the moof box. The runs of other tracks are read for this as well.
+    let mut data_end = moof_offset as u64;
+
+    for (_, traf) in Boxes::new(moof).filter(|(name, _)| name == b"traf") {
+        let tfhd = find(traf, b"tfhd").ok_or("Missing tfhd box")?;
+        let traf_track_id = read_u32(tfhd, 4).ok_or("Missing track id")?;
+        let defaults = SampleDefaults::of(defaults, traf_track_id);
+
+        let mut reader = FieldReader { data: tfhd, position: 8 };
+        let tf_flags = read_u32(tfhd, 0).unwrap_or(0) & 0xFFFFFF;
+        let base_offset = match (tf_flags & 0x1, tf_flags & 0x20000) {
+            (0, 0) => data_end,
+            // default-base-is-moof
+            (0, _) => moof_offset as u64,
+            _ => reader.u64()?,
+        };
+        if tf_flags & 0x2 != 0 {
+            reader.u32()?;
+        }
+        let duration = if tf_flags & 0x8 != 0 { reader.u32()? } else { defaults.duration };
+        let size = if tf_flags & 0x10 != 0 { reader.u32()? } else { defaults.size };
+        let flags = if tf_flags & 0x20 != 0 { reader.u32()? } else { defaults.flags };
+
+        let mut traf_decode_time = decode_time;
+        if let Some(tfdt) = find(traf, b"tfdt") {
+            let mut reader = FieldReader { data: tfdt, position: 4 };
+            traf_decode_time = match tfdt.first() {
+                Some(1) => reader.u64()?,
+                _ => reader.u32()? as u64,
+            };
+        }
+
+        let mut data_offset = base_offset;
+
+        for (_, trun) in Boxes::new(traf).filter(|(name, _)| name == b"trun") {
+            let mut reader = FieldReader { data: trun, position: 4 };
+            let tr_flags = read_u32(trun, 0).unwrap_or(0) & 0xFFFFFF;
+            let sample_count = reader.u32()?;
+
+            // A run without data offset continues after the previous run
+            if tr_flags & 0x1 != 0 {
+                data_offset = (base_offset as i64 + reader.u32()? as i32 as i64) as u64;
+            }
+            let first_sample_flags = if tr_flags & 0x4 != 0 { Some(reader.u32()?) } else { None };
+
+            for idx in 0..sample_count {
+                let sample_duration = if tr_flags & 0x100 != 0 { reader.u32()? } else { duration };
+                let sample_size = if tr_flags & 0x200 != 0 { reader.u32()? } else { size };
+                let sample_flags = if tr_flags & 0x400 != 0 { reader.u32()? } else { flags };
+                // Signed in version 1, but offsets don't get near 2^31 either way
+                let composition_offset = if tr_flags & 0x800 != 0 { reader.u32()? as i32 } else { 0 };
+
+                let sample_flags = match (idx, first_sample_flags) {
+                    (0, Some(first_sample_flags)) => first_sample_flags,
+                    _ => sample_flags,
+                };
+
+                if traf_track_id == track_id {
+                    samples.push(FragmentSample {
+                        offset: data_offset as usize,
+                        size: sample_size,
+                        decode_time: traf_decode_time,
+                        duration: sample_duration,
+                        composition_offset,
+                        keyframe: sample_flags & SAMPLE_IS_NON_SYNC == 0,
+                    });
+                }
+
+                data_offset += sample_size as u64;
+                traf_decode_time += sample_duration as u64;
+            }
+        }
+
+        data_end = data_offset;
+        if traf_track_id == track_id {
+            decode_time = traf_decode_time;
+        }
+    }
+
+    Ok((samples, decode_time))
+}
+
+struct FieldReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> FieldReader<'a> {
+    fn u32(&mut self) -> Result<u32, String> {
+        let value = read_u32(self.data, self.position).ok_or("Box is too small")?;
+        self.position += 4;
+        Ok(value)
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(((self.u32()? as u64) << 32) | self.u32()? as u64)
+    }
+}
+
+/// The first entry of a `stsd` box, only the size & child boxes of visual sample entries are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleEntry {
+    pub format: FourCC,
+    pub width: u16,
+    pub height: u16,
+    pub boxes: Vec<(FourCC, Vec<u8>)>,
+}
+
+// SampleEntry (8 bytes) + VisualSampleEntry fields (70 bytes)
+const VISUAL_SAMPLE_ENTRY_SIZE: usize = 78;
+
+impl SampleEntry {
+    /// Reads the sample entry of the track with the given id from a complete file
+    pub fn read(file: &[u8], track_id: u32) -> Option<Self> {
+        let moov = find(file, b"moov")?;
+        let trak = find_trak(moov, track_id)?;
+        let stsd = find_path(trak, &[b"mdia", b"minf", b"stbl", b"stsd"])?;
+
+        // version & flags + entry_count
+        let (format, entry) = Boxes::new(stsd.get(8..)?).next()?;
+        let width = entry.get(24..26).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]])).unwrap_or(0);
+        let height = entry.get(26..28).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]])).unwrap_or(0);
+        let boxes = entry
+            .get(VISUAL_SAMPLE_ENTRY_SIZE..)
+            .map(|children| Boxes::new(children).map(|(name, body)| (name, body.to_vec())).collect())
+            .unwrap_or_default();
+
+        Some(Self { format, width, height, boxes })
+    }
+
+    pub fn child(&self, name: &FourCC) -> Option<&[u8]> {
+        self.boxes.iter().find(|(found, _)| found == name).map(|(_, body)| body.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SampleEntry, SampleDefaults, FragmentSample, FragmentReader, read_fragment};
+
+    fn mp4_box(name: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(name);
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn it_reads_the_sample_entry_of_a_track() {
+        let tkhd = |track_id: u32| {
+            let mut body = vec![0; 12];
+            body.extend_from_slice(&track_id.to_be_bytes());
+            body.extend_from_slice(&[0; 64]);
+            mp4_box(b"tkhd", &body)
+        };
+        let mut entry = vec![0; 78];
+        entry[24..28].copy_from_slice(&[0x02, 0x80, 0x01, 0xE0]);
+        entry.extend(mp4_box(b"avcC", &[1, 0x64, 0x00, 0x1F]));
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(mp4_box(b"avc1", &entry));
+        let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+        let mdia = mp4_box(b"mdia", &mp4_box(b"minf", &stbl));
+
+        let mut audio = tkhd(1);
+        audio.extend(mp4_box(b"mdia", &[]));
+        let mut video = tkhd(2);
+        video.extend(mdia);
+        let mut moov = mp4_box(b"trak", &audio);
+        moov.extend(mp4_box(b"trak", &video));
+        let mut file = mp4_box(b"ftyp", b"isom");
+        file.extend(mp4_box(b"moov", &moov));
+
+        let entry = SampleEntry::read(&file, 2).unwrap();
+        assert_eq!(&entry.format, b"avc1");
+        assert_eq!((entry.width, entry.height), (640, 480));
+        assert_eq!(entry.child(b"avcC"), Some(&[1, 0x64, 0x00, 0x1F][..]));
+        assert_eq!(SampleEntry::read(&file, 1), None);
+    }
+
+    #[test]
+    fn it_reads_the_composition_offsets_of_a_fragment() {
+        let words = |words: &[u32]| words.iter().flat_map(|word| word.to_be_bytes()).collect::<Vec<u8>>();
+        // base-data-offset & default-sample-duration
+        let tfhd = words(&[0x09, 1, 0, 1000, 512]);
+        // data-offset, sample-flags, sample-size & composition-time-offset: I P B
+        let trun = words(&[0xE01, 3, 8, 100, 0, 1024, 50, 0x10000, 1536, 20, 0x10000, 512]);
+        let traf = [mp4_box(b"tfhd", &tfhd), mp4_box(b"trun", &trun)].concat();
+
+        let (samples, decode_time) = read_fragment(&mp4_box(b"traf", &traf), 0, 1, &[], 2048).unwrap();
+        assert_eq!(decode_time, 2048 + 3 * 512);
+        assert_eq!(samples[0], FragmentSample { offset: 1008, size: 100, decode_time: 2048, duration: 512, composition_offset: 1024, keyframe: true });
+        assert_eq!((samples[1].offset, samples[1].composition_offset, samples[1].keyframe), (1108, 1536, false));
+        assert_eq!((samples[2].decode_time, samples[2].composition_offset), (3072, 512));
+    }
+
+    #[test]
+    fn it_places_track_fragments_after_the_previous_one() {
+        let words = |words: &[u32]| words.iter().flat_map(|word| word.to_be_bytes()).collect::<Vec<u8>>();
+        // Two samples of another track at 100 with sample-size, then a sample of this track with
+        // default-sample-duration, neither has a base-data-offset
+        let audio = [mp4_box(b"tfhd", &words(&[0, 2])), mp4_box(b"trun", &words(&[0x201, 2, 100, 10, 20]))].concat();
+        let video = [mp4_box(b"tfhd", &words(&[0x08, 1, 512])), mp4_box(b"trun", &words(&[0x200, 1, 50]))].concat();
+        let moof = [mp4_box(b"traf", &audio), mp4_box(b"traf", &video)].concat();
+
+        let (samples, decode_time) = read_fragment(&moof, 0, 1, &[], 2048).unwrap();
+        assert_eq!(samples, vec![FragmentSample { offset: 130, size: 50, decode_time: 2048, duration: 512, composition_offset: 0, keyframe: true }]);
+        assert_eq!(decode_time, 2048 + 512);
+
+        // default-base-is-moof
+        let video = [mp4_box(b"tfhd", &words(&[0x20008, 1, 512])), mp4_box(b"trun", &words(&[0x200, 1, 50]))].concat();
+        let moof = [mp4_box(b"traf", &audio), mp4_box(b"traf", &video)].concat();
+        let defaults = [(1, SampleDefaults { duration: 0, size: 0, flags: 0x10000 })];
+        let (samples, _) = read_fragment(&moof, 64, 1, &defaults, 0).unwrap();
+        assert_eq!((samples[0].offset, samples[0].keyframe), (64, false));
+    }
+
+    #[test]
+    fn it_rejects_boxes_smaller_than_their_header() {
+        let mut data = mp4_box(b"ftyp", b"isom");
+        data.extend_from_slice(&[0, 0, 0, 4]);
+        data.extend_from_slice(b"moof");
+        assert!(FragmentReader::new().next(&data, 0).is_err());
+
+        // A size of 1 with a large size of 0
+        let mut data = vec![0, 0, 0, 1];
+        data.extend_from_slice(b"moof");
+        data.extend_from_slice(&[0; 8]);
+        assert!(FragmentReader::new().next(&data, 0).is_err());
+
+        // A box that runs past the end of memory
+        let mut data = mp4_box(b"ftyp", b"isom");
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&(usize::MAX as u64).to_be_bytes());
+        assert!(FragmentReader::new().next(&data, 0).is_err());
+
+        // Incomplete boxes are waited for
+        assert_eq!(FragmentReader::new().next(&mp4_box(b"moof", b"data")[..6], 0), Ok(None));
+    }
+}
@@ -1,11 +1,28 @@
-use std::io::Cursor;
+use std::{io::Cursor, rc::Rc};
 
 use crate::video::frames::FrameCache;
-use super::{VideoFile, frames::FrameCacheStore, CodecPrivate, util::Bits, av1::Av1, vpcc::Vpcc};
+use matroska_demuxer::{Primaries, TransferCharacteristics, MatrixCoefficients, Range, DisplayUnit};
+
+use super::{VideoFile, frames::FrameCacheStore, CodecPrivate, av1::{Av1, SequenceHeader}, vpcc::Vpcc, vp9, hevc::Hevc, ebml::{self, BlockReader}, color::{ColorSpace, ColorInfo, ContentLightLevel, MasteringDisplay}, display::{self, Orientation}, timebase::Timebase, timeline::Timeline};
 
 pub struct MkvVideoFile {
-    file: matroska_demuxer::MatroskaFile<Cursor<Vec<u8>>>,
+    file: matroska_demuxer::MatroskaFile<Cursor<Rc<[u8]>>>,
+    blocks: BlockReader,
     video_track: u64,
+    timebase: Timebase,
+    // The DefaultDuration of the track in microseconds
+    default_duration: Option<u64>,
+    timeline: Timeline,
+    orientation: Orientation,
+    // The first keyframe of a VP9 track without CodecPrivate, which libvpx doesn't write
+    vp9_keyframe: Option<vp9::FrameHeader>,
+    // The sequence header of the first keyframe of an AV1 track without configOBUs
+    av1_sequence_header: Option<SequenceHeader>,
+    // The start, size in bytes & duration in microseconds of the first cluster, the VP9 level is
+    // estimated from its bitrate
+    first_cluster: Option<(usize, u64, u64)>,
+    // The configuration read from the first keyframe, once the first cluster is complete
+    vp9_vpcc: Option<Vpcc>,
 }
 
 impl From<matroska_demuxer::DemuxError> for super::DemuxError {
@@ -17,26 +34,6 @@ impl From<matroska_demuxer::DemuxError> for super::DemuxError {
     }
 }
 
-impl Av1 {
-    fn from(codec_private: &[u8]) -> Result<Self, String> {
-        let mut bits = Bits::new(codec_private);
-        bits.skip(1, "marker")?;
-        bits.skip(7, "version")?;
-        let seq_profile = bits.read_u8(3, "seq_profile")?;
-        let seq_level_idx_0 = bits.read_u8(5, "seq_level_idx_0")?;
-        let seq_tier_0 = bits.read_bool("seq_tier_0")?;
-        let high_bitdepth = bits.read_bool("high_bitdepth")?;
-        let twelve_bit = bits.read_bool("twelve_bit")?;
-        Ok(Self {
-            seq_profile,
-            seq_level_idx_0,
-            seq_tier_0,
-            high_bitdepth,
-            twelve_bit,
-        })
-    }
-}
-
 #[derive(Debug, PartialEq, Eq)]
 enum VpccFeature {
     Profile = 1,
@@ -119,15 +116,15 @@ impl<'a> VpccCodecPrivateReader<'a> {
             features.push(result);
         }
 
-        Ok(Vpcc { 
-            profile: VpccCodecPrivateReader::get_feature(&features, VpccFeature::Profile)?, 
-            level: VpccCodecPrivateReader::get_feature(&features, VpccFeature::Level)?, 
-            bit_depth: VpccCodecPrivateReader::get_feature(&features, VpccFeature::BitDepth)?, 
-            chroma_subsampling: VpccCodecPrivateReader::get_feature(&features, VpccFeature::ChromaSubsampling)?
-        })
+        Ok(Vpcc::new(
+            VpccCodecPrivateReader::get_feature(&features, VpccFeature::Profile)?,
+            VpccCodecPrivateReader::get_feature(&features, VpccFeature::Level)?,
+            VpccCodecPrivateReader::get_feature(&features, VpccFeature::BitDepth)?,
+            VpccCodecPrivateReader::get_feature(&features, VpccFeature::ChromaSubsampling)?,
+        ))
     }
 
-    fn get_feature(features: &Vec<(VpccFeature, u8)>, feature: VpccFeature) -> Result<u8, String> {
+    fn get_feature(features: &[(VpccFeature, u8)], feature: VpccFeature) -> Result<u8, String> {
         features
                 .iter()
                 .find(|(f, _)| *f == feature).map(|(_, value)| *value)
@@ -136,13 +133,13 @@ impl<'a> VpccCodecPrivateReader<'a> {
 }
 
 impl Vpcc {
-    fn from(codec_private: &[u8]) -> Result<Self, String> {
+    fn from_codec_private(codec_private: &[u8]) -> Result<Self, String> {
         let mut reader = VpccCodecPrivateReader::from(codec_private);
         reader.read()
     }
 }
 
-fn find_video_track(file: &matroska_demuxer::MatroskaFile<Cursor<Vec<u8>>>) -> super::Result<u64> {
+fn find_video_track(file: &matroska_demuxer::MatroskaFile<Cursor<Rc<[u8]>>>) -> super::Result<u64> {
     for track  in file.tracks().iter() {
         match track.video() {
             Some(_) => return Ok(track.track_number().get()),
@@ -153,13 +150,92 @@ fn find_video_track(file: &matroska_demuxer::MatroskaFile<Cursor<Vec<u8>>>) -> s
     Err(super::DemuxError::TrackNotFound("Could not find video track".to_string()))
 }
 
+/// Returns the ISO/IEC 23091-2 code point of a Colour enum value, the enums only convert from it.
+fn code_point<T: From<u64> + PartialEq>(value: T) -> Option<u8> {
+    // 2 is "unspecified" for primaries, transfer and matrix
+    if value == T::from(2) {
+        return None;
+    }
+
+    (0..=u8::MAX).find(|code| T::from(*code as u64) == value)
+}
+
+fn mastering_display(metadata: &matroska_demuxer::MasteringMetadata) -> Option<MasteringDisplay> {
+    Some(MasteringDisplay {
+        red: (metadata.primary_r_chromaticity_x()?, metadata.primary_r_chromaticity_y()?),
+        green: (metadata.primary_g_chromaticity_x()?, metadata.primary_g_chromaticity_y()?),
+        blue: (metadata.primary_b_chromaticity_x()?, metadata.primary_b_chromaticity_y()?),
+        white_point: (metadata.white_point_chromaticity_x()?, metadata.white_point_chromaticity_y()?),
+        max_luminance: metadata.luminance_max()?,
+        min_luminance: metadata.luminance_min().unwrap_or(0.0),
+    })
+}
+
 impl MkvVideoFile {
-    pub fn init(buffer: Vec<u8>) -> super::Result<Self> {
-        let cursor = Cursor::new(buffer);
+    pub fn init(buffer: Rc<[u8]>) -> super::Result<Self> {
+        let cursor = Cursor::new(buffer.clone());
         let file = matroska_demuxer::MatroskaFile::open(cursor)?;
         let video_track = find_video_track(&file)?;
+        let timebase = Timebase::from_nanoseconds(file.info().timestamp_scale().get());
+        let track = file.tracks().iter().find(|track| track.track_number().get() == video_track);
+        let nanoseconds = Timebase::from_nanoseconds(1);
+        let default_duration = track
+            .and_then(|track| track.default_duration())
+            .map(|duration| nanoseconds.to_micros(duration.get()));
+        // The frames within the CodecDelay are only decoded
+        let timeline = match track.and_then(|track| track.codec_delay()).filter(|delay| *delay > 0) {
+            Some(delay) => Timeline::shift(nanoseconds.to_micros(delay)),
+            None => Timeline::default(),
+        };
+
+        // The demuxer doesn't read the Projection
+        let orientation = match ebml::projection_pose(&buffer, video_track).map_err(super::DemuxError::InvalidData)? {
+            Some((yaw, pitch, roll)) => Orientation::from_projection(yaw, pitch, roll),
+            None => Orientation::default(),
+        };
+
+        Ok(MkvVideoFile { file, blocks: BlockReader::new(), video_track, timebase, default_duration, timeline, orientation, vp9_keyframe: None, av1_sequence_header: None, first_cluster: None, vp9_vpcc: None })
+    }
 
-        Ok(MkvVideoFile { file, video_track })
+    /// Opens the file from the start of it, once the headers are complete
+    pub fn open(data: &[u8]) -> super::Result<Option<Self>> {
+        match ebml::header(data).map_err(super::DemuxError::InvalidData)? {
+            Some(header) => Ok(Some(Self::init(header.into())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The configuration read from the first keyframe, the level is estimated from the part of the
+    /// first cluster read so far, which is the whole cluster once it is frozen in `vp9_vpcc`
+    fn vp9_keyframe_vpcc(&self) -> Option<Vpcc> {
+        let header = self.vp9_keyframe?;
+        // In kbit/s
+        let bitrate = self.first_cluster
+            .filter(|(_, _, duration)| *duration > 0)
+            .map(|(_, size, duration)| size as f64 * 8_000.0 / duration as f64);
+
+        Some(header.to_vpcc(vp9::estimate_level(header.width, header.height, self.frame_rate(), bitrate)))
+    }
+
+    /// The colours of the AV1 sequence header or the VP9 keyframe
+    fn codec_color_space(&self) -> Option<ColorSpace> {
+        let track = self.file.tracks().iter().find(|track| track.track_number().get() == self.video_track)?;
+
+        match track.codec_id() {
+            "V_AV1" => Av1::from(track.codec_private()?).ok()?
+                .or_sequence_header(self.av1_sequence_header)
+                .sequence_header
+                .map(|header| header.color_space),
+            "V_VP9" => self.vp9_keyframe.map(|header| header.color_space()),
+            _ => None,
+        }
+    }
+
+    fn video(&self) -> Option<&matroska_demuxer::Video> {
+        self.file.tracks()
+            .iter()
+            .find(|track| track.track_number().get() == self.video_track)
+            .and_then(|track| track.video())
     }
 }
 
@@ -172,15 +248,17 @@ impl VideoFile for MkvVideoFile {
 
             match track.codec_id() {
                 "V_AV1" => return track.codec_private()
-                    .map(|codec_private| Av1::from(codec_private).ok())
-                    .flatten()
-                    .map(|seq| seq.to_codec_string()),
+                    .and_then(|codec_private| Av1::from(codec_private).ok())
+                    .map(|av1| av1.or_sequence_header(self.av1_sequence_header).to_codec_string()),
                 // Codec private data SHOULD be set according to webm spec, but videos encoded using vpx-vp9 never set this data
                 "V_VP9" => return track.codec_private()
-                    .map(|codec_private| Vpcc::from(codec_private).ok())
-                    .flatten()
-                    .map(|seq| seq.to_codec_string()),
+                    .and_then(|codec_private| Vpcc::from_codec_private(codec_private).ok())
+                    .or_else(|| self.vp9_vpcc.or_else(|| self.vp9_keyframe_vpcc()))
+                    .map(|vpcc| vpcc.with_color_space(self.color_info().color_space).to_codec_string()),
                 "V_VP8" => return Some("vp8".to_string()),
+                "V_MPEGH/ISO/HEVC" => return track.codec_private()
+                    .and_then(|codec_private| Hevc::from("hvc1", codec_private).ok())
+                    .map(|hvcc| hvcc.to_codec_string()),
                 // TODO: More codecs?
                 _ => return None,
             };
@@ -189,6 +267,22 @@ impl VideoFile for MkvVideoFile {
         None
     }
 
+    fn description(&self) -> Option<Vec<u8>> {
+        for track in self.file.tracks().iter() {
+            if track.track_number().get() != self.video_track {
+                continue;
+            }
+
+            return match track.codec_id() {
+                // CodecPrivate holds the hvcC record
+                "V_MPEGH/ISO/HEVC" => track.codec_private().map(|codec_private| codec_private.to_vec()),
+                _ => None,
+            };
+        }
+
+        None
+    }
+
     fn coded_width(&self) -> super::Result<u32> {
         for track in self.file.tracks().iter() {
             if track.track_number().get() != self.video_track {
@@ -211,7 +305,7 @@ impl VideoFile for MkvVideoFile {
             }
 
             match track.video() {
-                Some(video) => return Ok(video.pixel_width().get() as u32),
+                Some(video) => return Ok(video.pixel_height().get() as u32),
                 None => return Err(super::DemuxError::TrackNotFound("Could not find video track".to_string())),
             }
         }
@@ -219,32 +313,139 @@ impl VideoFile for MkvVideoFile {
         Err(super::DemuxError::TrackNotFound("Could not find video track".to_string()))
     }
 
+    fn display_size(&self) -> Option<(u32, u32)> {
+        let video = self.video()?;
+        let width = video.display_width().unwrap_or(video.pixel_width()).get();
+        let height = video.display_height().unwrap_or(video.pixel_height()).get();
+
+        match video.display_unit() {
+            None | Some(DisplayUnit::Pixels) => Some((width as u32, height as u32)),
+            Some(DisplayUnit::Unknown) => None,
+            // Centimeters, inches & aspect ratios only give the shape, the height is kept
+            Some(_) => {
+                let pixel_height = video.pixel_height().get();
+                Some(((pixel_height * width / height) as u32, pixel_height as u32))
+            },
+        }
+    }
+
+    fn pixel_aspect_ratio(&self) -> Option<(u32, u32)> {
+        let video = self.video()?;
+        let (width, height) = self.display_size()?;
+
+        display::aspect_ratio(width as u64 * video.pixel_height().get(), height as u64 * video.pixel_width().get())
+    }
+
+    fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    fn color_info(&self) -> ColorInfo {
+        let container = match self.video().and_then(|video| video.colour()) {
+            Some(colour) => ColorInfo {
+                color_space: ColorSpace {
+                    primaries: colour.primaries().and_then(code_point::<Primaries>),
+                    transfer: colour.transfer_characteristics().and_then(code_point::<TransferCharacteristics>),
+                    matrix: colour.matrix_coefficients().and_then(code_point::<MatrixCoefficients>),
+                    full_range: match colour.range() {
+                        Some(Range::Broadcast) => Some(false),
+                        Some(Range::Full) => Some(true),
+                        _ => None,
+                    },
+                },
+                mastering_display: colour.mastering_metadata().and_then(mastering_display),
+                content_light_level: match (colour.max_cll(), colour.max_fall()) {
+                    (None, None) => None,
+                    (max_cll, max_fall) => Some(ContentLightLevel {
+                        max_content_light_level: max_cll.unwrap_or(0).min(u16::MAX as u64) as u16,
+                        max_frame_average_light_level: max_fall.unwrap_or(0).min(u16::MAX as u64) as u16,
+                    }),
+                },
+            },
+            None => ColorInfo::default(),
+        };
+
+        // The codec configuration or first keyframe the codec string comes from describes the
+        // colours as well, Colour takes precedence
+        match self.codec_color_space() {
+            Some(color_space) => container.or(ColorInfo { color_space, ..ColorInfo::default() }),
+            None => container,
+        }
+    }
+
     fn duration(&self) -> super::Result<f64> {
-        self.file.info().duration().ok_or(super::DemuxError::InvalidData("No duration".to_string()))
+        let duration = self.file.info().duration().ok_or(super::DemuxError::InvalidData("No duration".to_string()))?;
+
+        Ok(self.timebase.to_seconds(duration))
     }
 
-    fn keyframes(&mut self) -> super::Result<super::frames::FrameCacheStore> {
-        let mut store = Vec::new();
+    fn frame_rate(&self) -> Option<f64> {
+        let track = self.file.tracks().iter().find(|track| track.track_number().get() == self.video_track)?;
 
-        let mut frame = matroska_demuxer::Frame::default();
-        loop {
-            if !self.file.next_frame(&mut frame)? {
-                break;
-            }
+        // DefaultDuration is in nanoseconds
+        Some(1_000_000_000.0 / track.default_duration()?.get() as f64)
+    }
+
+    fn needs_keyframe(&self) -> bool {
+        let track = match self.file.tracks().iter().find(|track| track.track_number().get() == self.video_track) {
+            Some(track) => track,
+            None => return false,
+        };
 
-            if frame.track != self.video_track {
+        match track.codec_id() {
+            "V_VP9" => self.vp9_vpcc.is_none() && track.codec_private().is_none(),
+            "V_AV1" => self.av1_sequence_header.is_none() && track.codec_private()
+                .and_then(|codec_private| Av1::from(codec_private).ok())
+                .is_some_and(|av1| av1.sequence_header.is_none()),
+            _ => false,
+        }
+    }
+
+    fn read_keyframe(&mut self, frame: &[u8]) {
+        match self.file.tracks().iter().find(|track| track.track_number().get() == self.video_track).map(|track| track.codec_id()) {
+            Some("V_VP9") if self.vp9_keyframe.is_none() => self.vp9_keyframe = vp9::FrameHeader::from(frame).ok().flatten(),
+            // The temporal unit of a keyframe starts with the sequence header
+            Some("V_AV1") => self.av1_sequence_header = SequenceHeader::find(frame).ok().flatten(),
+            _ => {},
+        }
+    }
+
+    fn read_frames(&mut self, data: &[u8], offset: usize, frames: &mut FrameCacheStore) -> super::Result<()> {
+        while let Some(block) = self.blocks.next(data, offset).map_err(super::DemuxError::InvalidData)? {
+            if block.track != self.video_track {
                 continue;
             }
 
-            let keyframe = frame.is_keyframe.unwrap_or(false);
+            let ticks = block.timestamp.max(0) as u64;
+            let timestamp = self.timebase.to_micros(ticks);
+            let duration = block.duration
+                .map(|duration| self.timebase.duration_to_micros(ticks, duration))
+                .or(self.default_duration);
+
+            let presentation = self.timeline.map(timestamp, duration.unwrap_or(0));
+
+            // The first block of the next cluster completes the first one
+            let first_cluster = self.first_cluster.get_or_insert((block.cluster, 0, 0));
+            if first_cluster.0 == block.cluster {
+                first_cluster.1 += block.frames.iter().map(|(_, size)| *size as u64).sum::<u64>();
+                first_cluster.2 += duration.unwrap_or(0);
+            } else if self.vp9_vpcc.is_none() {
+                self.vp9_vpcc = self.vp9_keyframe_vpcc();
+            }
 
-            let ts = frame.timestamp as f64;
-            let chunk = FrameCache::init(&frame.data, ts, keyframe)?;
+            for (frame_offset, size) in block.frames.iter().copied() {
+                if block.keyframe && self.needs_keyframe() {
+                    self.read_keyframe(data.get(frame_offset - offset..frame_offset - offset + size).unwrap_or_default());
+                }
+            }
 
-            store.push(chunk);
+            for (offset, size) in block.frames {
+                // Unknown durations are filled in from the next frame in presentation order
+                frames.push(FrameCache { duration, ..FrameCache::present(offset, size as u32, presentation, block.keyframe) });
+            }
         }
 
-        Ok(FrameCacheStore::new(store)?)
+        Ok(())
     }
 }
 
@@ -252,11 +453,11 @@ impl VideoFile for MkvVideoFile {
 mod tests {
     use matroska_demuxer::TrackType;
 
-    use crate::video::{mkv::VpccCodecPrivateReader, vpcc::Vpcc};
+    use crate::video::{mkv::{MkvVideoFile, VpccCodecPrivateReader}, vpcc::Vpcc, test_util::{read_frames, sample_video}, VideoFile};
 
     #[test]
     fn it_works_on_video_generated_with_ffmpeg() {
-        let vpcc_expected = Vpcc { profile: 1, level: 31, bit_depth: 8, chroma_subsampling: 3};
+        let vpcc_expected = Vpcc::new(1, 31, 8, 3);
         let file = std::fs::File::open("data/test_vp9_codec_private.webm").unwrap();
         let mkv = matroska_demuxer::MatroskaFile::open(file).unwrap();
         let track = mkv.tracks().iter().find(|t| t.track_type() == TrackType::Video).unwrap();
@@ -268,7 +469,7 @@ mod tests {
 
     #[test]
     fn it_works_with_valid_codec_private_data() {
-        let vpcc_expected = Vpcc { profile: 1, level: 31, bit_depth: 8, chroma_subsampling: 3};
+        let vpcc_expected = Vpcc::new(1, 31, 8, 3);
         let data: &[u8] = &[
             1, 1, 1, // Profile: 1
             2, 1, 31, // Level: 31
@@ -319,4 +520,51 @@ mod tests {
 
         assert_eq!(vpcc, Err("Missing feature Profile".into()));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn it_reads_the_video_track_of_a_sample_video() {
+        let file = sample_video("video_2s_30fps_640x480_vp8.webm", MkvVideoFile::init);
+
+        assert_eq!(file.codec(), Some("vp8".to_string()));
+        assert_eq!(file.coded_width().unwrap(), 640);
+        assert_eq!(file.coded_height().unwrap(), 480);
+        assert_eq!(file.duration().unwrap(), 2.0);
+    }
+
+    #[test]
+    fn it_reads_all_frames_of_a_sample_video() {
+        let buffer = std::fs::read("../../examples/src/assets/videos/video_5s_30fps_640x480_vp9.mkv").unwrap();
+        let mut file = MkvVideoFile::open(&buffer).unwrap().unwrap();
+        let store = read_frames(&mut file, &buffer);
+
+        assert_eq!(store.count(), 150);
+        assert!(store.get(0).unwrap().keyframe);
+        assert!(store.get(128).unwrap().keyframe);
+        assert_eq!(store.find_prev_key_frame_before(127), Some(0));
+        assert_eq!(store.find_prev_key_frame_before(140), Some(128));
+        assert_eq!(store.timestamp_to_frame(store.get(42).unwrap().timestamp), Some(42));
+        assert_eq!(store.get(30).unwrap().timestamp, 1_000_000);
+        // The DefaultDuration of the track, the frames have no BlockDuration
+        assert_eq!(store.duration(149), Some(33333));
+        assert_eq!(file.frame_rate().map(f64::round), Some(30.0));
+        // The file has no CodecPrivate, it is read from the first keyframe
+        assert_eq!(file.codec(), Some("vp09.01.30.08.03.02.02.00.01".to_string()));
+        assert_eq!((file.color_info().color_space.matrix, file.color_info().color_space.full_range), (Some(0), Some(true)));
+    }
+
+    #[test]
+    fn it_resolves_the_same_frames_as_the_matroska_demuxer() {
+        let buffer: std::rc::Rc<[u8]> = std::fs::read("../../examples/src/assets/videos/video_2s_30fps_640x480_av1.webm").unwrap().into();
+        let mut file = MkvVideoFile::init(buffer.clone()).unwrap();
+        let store = read_frames(&mut file, &buffer);
+        let mut frame = matroska_demuxer::Frame::default();
+        assert_eq!(file.codec(), Some("av01.1.04M.08.0.000.01.13.00.1".to_string()));
+
+        for idx in 0..store.count() {
+            assert!(file.file.next_frame(&mut frame).unwrap());
+            let cached = store.get(idx).unwrap();
+            assert_eq!(cached.data(&buffer), Some(&frame.data[..]));
+            assert_eq!(cached.timestamp, file.timebase.to_micros(frame.timestamp) as i64);
+        }
+    }
+}
@@ -1,13 +1,33 @@
-use self::frames::FrameCacheStore;
+use std::fmt;
+
+use self::{frames::FrameCacheStore, color::ColorInfo, display::Orientation};
 
 mod util;
 pub mod mp4;
 pub mod mkv;
 pub mod frames;
+pub mod color;
+pub mod display;
+pub mod config;
+pub mod format;
+pub mod stream;
+pub mod range;
+pub mod timebase;
+pub mod timeline;
+pub mod plan;
+pub mod rate;
 
 mod vpcc;
+mod vp9;
 mod av1;
+mod avc;
+mod hevc;
+mod ebml;
+mod isobmff;
+#[cfg(test)]
+mod test_util;
 
+#[derive(Debug)]
 pub enum DemuxError {
     Io(String),
     InvalidData(String),
@@ -16,14 +36,14 @@ pub enum DemuxError {
     NoFrames(String),
 }
 
-impl ToString for DemuxError {
-    fn to_string(&self) -> String {
+impl fmt::Display for DemuxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            DemuxError::Io(msg) => msg.clone(),
-            DemuxError::InvalidData(msg) => msg.clone(),
-            DemuxError::TrackNotFound(msg) => msg.clone(),
-            DemuxError::Unknown(msg) => msg.clone(),
-            DemuxError::NoFrames(msg) => msg.clone(),
+            DemuxError::Io(msg) => f.write_str(msg),
+            DemuxError::InvalidData(msg) => f.write_str(msg),
+            DemuxError::TrackNotFound(msg) => f.write_str(msg),
+            DemuxError::Unknown(msg) => f.write_str(msg),
+            DemuxError::NoFrames(msg) => f.write_str(msg),
         }
     }
 }
@@ -32,10 +52,30 @@ type Result<T> = std::result::Result<T, DemuxError>;
 
 pub trait VideoFile {
     fn codec(&self) -> Option<String>;
+    /// The codec specific configuration the decoder has to be configured with, if any
+    fn description(&self) -> Option<Vec<u8>>;
     fn coded_width(&self) -> Result<u32>;
     fn coded_height(&self) -> Result<u32>;
+    /// The size the frames should be displayed at, when it differs from the coded size
+    fn display_size(&self) -> Option<(u32, u32)>;
+    /// The width of a pixel relative to its height, when the pixels aren't square
+    fn pixel_aspect_ratio(&self) -> Option<(u32, u32)>;
+    fn orientation(&self) -> Orientation;
+    /// The colours of the container, completed with those of the codec configuration
+    fn color_info(&self) -> ColorInfo;
+    /// The duration in seconds
     fn duration(&self) -> Result<f64>;
-    fn keyframes(&mut self) -> Result<FrameCacheStore>;
+    /// The frames per second the container declares
+    fn frame_rate(&self) -> Option<f64>;
+    /// Whether the codec string still lacks parameters that are only in the first keyframe
+    fn needs_keyframe(&self) -> bool;
+    /// Reads those parameters from the data of the first keyframe
+    fn read_keyframe(&mut self, frame: &[u8]);
+    /// Adds the frames that became available in `data` to `frames`, with their timestamps in
+    /// microseconds. `data` is the part of the file starting at `offset` that has been loaded so
+    /// far, it is called again with the same or a later part of the file whenever more has been
+    /// loaded.
+    fn read_frames(&mut self, data: &[u8], offset: usize, frames: &mut FrameCacheStore) -> Result<()>;
 }
 
 trait CodecPrivate {
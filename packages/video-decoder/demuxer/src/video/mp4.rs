@@ -1,14 +1,24 @@
-use std::io::Cursor;
+use std::{io::Cursor, rc::Rc};
 
-use mp4::Mp4Reader;
+use crate::video::frames::FrameCache;
 
-use crate::{video::frames::FrameCache, console_warn};
-
-use super::{VideoFile, frames::FrameCacheStore, CodecPrivate, vpcc::Vpcc};
+use super::{VideoFile, frames::FrameCacheStore, CodecPrivate, vpcc::Vpcc, av1::{Av1, SequenceHeader}, avc::Avc, hevc::Hevc, isobmff::{self, SampleEntry, SampleDefaults, FragmentReader}, color::{ColorSpace, ColorInfo, ContentLightLevel, MasteringDisplay}, display::{self, Orientation}, timebase::Timebase, timeline::{Timeline, Edit}};
 
 pub struct Mp4VideoFile {
-    file: mp4::Mp4Reader<Cursor<Vec<u8>>>,
+    file: mp4::Mp4Reader<Cursor<Rc<[u8]>>>,
     video_track: u32,
+    sample_entry: Option<SampleEntry>,
+    timebase: Timebase,
+    timeline: Timeline,
+    samples_read: bool,
+    // Fragmented files describe their samples in `moof` boxes after the `moov` box
+    fragments: FragmentReader,
+    sample_defaults: Vec<(u32, SampleDefaults)>,
+    decode_time: u64,
+    // The offset & size of the first keyframe, and the AV1 sequence header read from it when the
+    // configOBUs don't have one
+    first_keyframe: Option<(usize, u32)>,
+    av1_sequence_header: Option<SequenceHeader>,
 }
 
 impl From<mp4::Error> for super::DemuxError {
@@ -31,7 +41,7 @@ impl From<super::frames::FrameCacheError> for super::DemuxError {
     }
 }
 
-fn find_video_track(file: &mp4::Mp4Reader<Cursor<Vec<u8>>>) -> super::Result<u32> {
+fn find_video_track(file: &mp4::Mp4Reader<Cursor<Rc<[u8]>>>) -> super::Result<u32> {
     for (id, track) in file.tracks().iter() {
         match track.track_type() {
             Ok(track_type) => if track_type == mp4::TrackType::Video {
@@ -45,49 +55,115 @@ fn find_video_track(file: &mp4::Mp4Reader<Cursor<Vec<u8>>>) -> super::Result<u32
 }
 
 impl Mp4VideoFile {
-    pub fn init(buffer: Vec<u8>) -> super::Result<Self> {
+    pub fn init(buffer: Rc<[u8]>) -> super::Result<Self> {
         let size = buffer.len() as u64;
-        let cursor = Cursor::new(buffer);
+        let cursor = Cursor::new(buffer.clone());
         let file = mp4::Mp4Reader::read_header(cursor, size)?;
         let video_track = find_video_track(&file)?;
+        let sample_entry = SampleEntry::read(&buffer, video_track);
+        let sample_defaults = SampleDefaults::read(&buffer);
+        let timebase = Timebase::from_timescale(file.tracks()[&video_track].timescale());
+        let timeline = edit_list(&file, video_track, &timebase);
 
-        Ok(Mp4VideoFile { file, video_track })
+        Ok(Mp4VideoFile {
+            file,
+            video_track,
+            sample_entry,
+            timebase,
+            timeline,
+            samples_read: false,
+            fragments: FragmentReader::new(),
+            sample_defaults,
+            decode_time: 0,
+            first_keyframe: None,
+            av1_sequence_header: None,
+        })
     }
-}
 
-trait VideoCodec {
-    fn vpcc(&self) -> Option<Vpcc>;
-}
+    /// Opens the file from the start of it, once the `moov` box is complete
+    pub fn open(data: &[u8]) -> super::Result<Option<Self>> {
+        match isobmff::header(data) {
+            Some(header) => Ok(Some(Self::init(header.into())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn track(&self) -> Option<&mp4::Mp4Track> {
+        self.file.tracks().get(&self.video_track)
+    }
 
-impl<R: std::io::Read + std::io::Seek> VideoCodec for Mp4Reader<R> {
+    fn avc(&self) -> Option<Avc> {
+        let entry = self.sample_entry.as_ref()?;
+        match &entry.format {
+            b"avc1" | b"avc3" => Avc::from(std::str::from_utf8(&entry.format).ok()?, entry.child(b"avcC")?).ok(),
+            _ => None,
+        }
+    }
+
+    fn av1(&self) -> Option<Av1> {
+        let entry = self.sample_entry.as_ref()?;
+        match &entry.format {
+            b"av01" => Av1::from(entry.child(b"av1C")?).ok().map(|av1| av1.or_sequence_header(self.av1_sequence_header)),
+            _ => None,
+        }
+    }
+
+    // The vpcC box the mp4 crate reads lacks the colour primaries
     fn vpcc(&self) -> Option<Vpcc> {
-        for trak in self.moov.traks.iter() {
-            if let Some(vp09) = trak.mdia.minf.stbl.stsd.vp09.clone() {
-                return Some(Vpcc { 
-                    profile: vp09.vpcc.profile, 
-                    level: vp09.vpcc.level, 
-                    bit_depth: vp09.vpcc.bit_depth, 
-                    chroma_subsampling: vp09.vpcc.chroma_subsampling, 
-                })
-            }
+        let entry = self.sample_entry.as_ref()?;
+        match &entry.format {
+            b"vp09" => Vpcc::from(entry.child(b"vpcC")?).ok(),
+            _ => None,
         }
+    }
 
-        None
-    }  
+    fn hevc(&self) -> Option<Hevc> {
+        let entry = self.sample_entry.as_ref()?;
+        match &entry.format {
+            b"hvc1" | b"hev1" => Hevc::from(std::str::from_utf8(&entry.format).ok()?, entry.child(b"hvcC")?).ok(),
+            _ => None,
+        }
+    }
 }
 
 impl VideoFile for Mp4VideoFile {
     fn codec(&self) -> Option<String> {
-        if let Some(vpcc) = self.file.vpcc() {
+        if let Some(vpcc) = self.vpcc() {
             return Some(vpcc.to_codec_string())
         }
 
+        if let Some(avc) = self.avc() {
+            return Some(avc.to_codec_string())
+        }
+
+        if let Some(hevc) = self.hevc() {
+            return Some(hevc.to_codec_string())
+        }
+
+        if let Some(av1) = self.av1() {
+            return Some(av1.to_codec_string())
+        }
+
         // TODO: Other codecs
 
         None
     }
 
+    fn description(&self) -> Option<Vec<u8>> {
+        let entry = self.sample_entry.as_ref()?;
+        match &entry.format {
+            // AVC formatted bitstreams need the avcC record to be decoded
+            b"avc1" | b"avc3" => entry.child(b"avcC").map(|avcc| avcc.to_vec()),
+            b"hvc1" | b"hev1" => entry.child(b"hvcC").map(|hvcc| hvcc.to_vec()),
+            _ => None,
+        }
+    }
+
     fn coded_width(&self) -> super::Result<u32> {
+        if let Some(entry) = self.sample_entry.as_ref().filter(|entry| entry.width > 0) {
+            return Ok(entry.width as u32);
+        }
+
         for (id, track) in self.file.tracks().iter() {
             if self.video_track == *id {
                 return Ok(track.width() as u32);
@@ -98,6 +174,10 @@ impl VideoFile for Mp4VideoFile {
     }
 
     fn coded_height(&self) -> super::Result<u32> {
+        if let Some(entry) = self.sample_entry.as_ref().filter(|entry| entry.height > 0) {
+            return Ok(entry.height as u32);
+        }
+
         for (id, track) in self.file.tracks().iter() {
             if self.video_track == *id {
                 return Ok(track.height() as u32);
@@ -107,27 +187,346 @@ impl VideoFile for Mp4VideoFile {
         Err(super::DemuxError::TrackNotFound("Could not find video track".to_string()))
     }
 
+    fn display_size(&self) -> Option<(u32, u32)> {
+        let tkhd = &self.track()?.trak.tkhd;
+
+        match (tkhd.width.value(), tkhd.height.value()) {
+            (0, _) | (_, 0) => None,
+            (width, height) => Some((width as u32, height as u32)),
+        }
+    }
+
+    fn pixel_aspect_ratio(&self) -> Option<(u32, u32)> {
+        // hSpacing & vSpacing
+        let pasp = self.sample_entry.as_ref()?.child(b"pasp")?;
+        let spacing = |range: std::ops::Range<usize>| pasp.get(range).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+
+        display::aspect_ratio(spacing(0..4)? as u64, spacing(4..8)? as u64)
+    }
+
+    fn orientation(&self) -> Orientation {
+        match self.track() {
+            Some(track) => {
+                let matrix = &track.trak.tkhd.matrix;
+                Orientation::from_matrix(matrix.a, matrix.b, matrix.c, matrix.d)
+            },
+            None => Orientation::default(),
+        }
+    }
+
+    fn color_info(&self) -> ColorInfo {
+        let entry = match self.sample_entry.as_ref() {
+            Some(entry) => entry,
+            None => return ColorInfo::default(),
+        };
+        let container = ColorInfo {
+            color_space: entry.child(b"colr").and_then(ColorSpace::from_colr).unwrap_or_default(),
+            mastering_display: entry.child(b"mdcv").and_then(MasteringDisplay::from_mdcv),
+            content_light_level: entry.child(b"clli").and_then(ContentLightLevel::from_clli),
+        };
+
+        // The codec configuration describes the colours as well, colr takes precedence
+        let codec = match self.vpcc() {
+            Some(vpcc) => vpcc.color_space(),
+            None => self.av1()
+                .and_then(|av1| av1.sequence_header)
+                .map(|header| header.color_space)
+                .unwrap_or_default(),
+        };
+
+        container.or(ColorInfo { color_space: codec, ..ColorInfo::default() })
+    }
+
     fn duration(&self) -> super::Result<f64> {
-        Ok(self.file.duration().as_secs_f64())
+        // The edit list says which part of the media is presented
+        match self.timeline.duration() {
+            Some(duration) => Ok(duration as f64 / 1_000_000.0),
+            None => Ok(self.file.duration().as_secs_f64()),
+        }
+    }
+
+    fn frame_rate(&self) -> Option<f64> {
+        let track = self.track()?;
+        let mut deltas: Vec<(u32, u32)> = track.trak.mdia.minf.stbl.stts.entries
+            .iter()
+            .map(|entry| (entry.sample_delta, entry.sample_count))
+            .collect();
+        // Fragmented files declare the duration of their samples in trex
+        if deltas.is_empty() {
+            deltas.push((SampleDefaults::of(&self.sample_defaults, self.video_track).duration, 1));
+        }
+
+        // The most common sample duration, a shorter last sample is common
+        let (delta, _) = deltas.into_iter().filter(|(delta, _)| *delta > 0).max_by_key(|(_, count)| *count)?;
+
+        Some(track.timescale() as f64 / delta as f64)
+    }
+
+    fn needs_keyframe(&self) -> bool {
+        self.av1().is_some_and(|av1| av1.sequence_header.is_none())
     }
 
-    fn keyframes(&mut self) -> super::Result<super::frames::FrameCacheStore> {
-        
-        let sample_count = self.file.sample_count(self.video_track)?;
-        let mut store = Vec::new();
+    fn read_keyframe(&mut self, frame: &[u8]) {
+        // The temporal unit of a keyframe starts with the sequence header
+        if self.av1().is_some() {
+            self.av1_sequence_header = SequenceHeader::find(frame).ok().flatten();
+        }
+    }
 
-        for idx in 0..sample_count {
-            let sample_id = idx + 1;
-            let maybe_sample = self.file.read_sample(self.video_track, sample_id)?;
+    fn read_frames(&mut self, data: &[u8], offset: usize, frames: &mut FrameCacheStore) -> super::Result<()> {
+        // The sample table describes all frames up front, the store tracks which are loaded
+        if !self.samples_read {
+            let track = self.track().ok_or(super::DemuxError::TrackNotFound("Could not find video track".to_string()))?;
+            for frame in sample_table(track, &self.timebase, &self.timeline)? {
+                if frame.keyframe {
+                    self.first_keyframe.get_or_insert((frame.offset, frame.size));
+                }
+                frames.push(frame);
+            }
+            self.samples_read = true;
+        }
+
+        // The frames of fragmented files follow once their `moof` box is complete
+        while let Some((moof_offset, moof)) = self.fragments.next(data, offset).map_err(super::DemuxError::InvalidData)? {
+            let (samples, decode_time) = isobmff::read_fragment(moof, moof_offset, self.video_track, &self.sample_defaults, self.decode_time)
+                .map_err(super::DemuxError::InvalidData)?;
 
-            if let Some(sample) = maybe_sample {
-                let el = FrameCache::init(&sample.bytes, sample.start_time as f64, sample.is_sync)?;
-                store.push(el);
-            } else {
-                console_warn!("Did not find sample for id: {sample_id}");
+            for sample in samples {
+                if sample.keyframe {
+                    self.first_keyframe.get_or_insert((sample.offset, sample.size));
+                }
+                let times = (sample.decode_time, sample.composition_offset, sample.duration);
+                frames.push(sample_frame(&self.timebase, &self.timeline, sample.offset, sample.size, times, sample.keyframe));
             }
+            self.decode_time = decode_time;
         }
 
-        Ok(FrameCacheStore::new(store)?)
+        if let Some((keyframe_offset, size)) = self.first_keyframe.filter(|_| self.needs_keyframe()) {
+            let start = keyframe_offset.wrapping_sub(offset);
+            if let Some(keyframe) = data.get(start..start.saturating_add(size as usize)) {
+                self.read_keyframe(keyframe);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the edit list of a track, the edits are in the movie timescale and the media times in
+/// the timescale of the track
+fn edit_list(file: &mp4::Mp4Reader<Cursor<Rc<[u8]>>>, track_id: u32, timebase: &Timebase) -> Timeline {
+    let elst = match file.tracks().get(&track_id).and_then(|track| track.trak.edts.as_ref()?.elst.as_ref()) {
+        Some(elst) => elst,
+        None => return Timeline::default(),
+    };
+    let movie_timebase = Timebase::from_timescale(file.moov.mvhd.timescale);
+    // -1 in the size of the version
+    let empty = if elst.version == 1 { u64::MAX } else { u32::MAX as u64 };
+
+    let mut start = 0;
+    let mut edits = Vec::with_capacity(elst.entries.len());
+    for entry in elst.entries.iter() {
+        // Fragmented files don't know the duration up front
+        let duration = Some(movie_timebase.to_micros(entry.segment_duration)).filter(|duration| *duration > 0);
+        edits.push(Edit {
+            start,
+            duration,
+            media_time: Some(entry.media_time).filter(|media_time| *media_time != empty).map(|media_time| timebase.to_micros(media_time)),
+            rate: entry.media_rate as f64 + entry.media_rate_fraction as f64 / 65536.0,
+        });
+        start += duration.unwrap_or(0);
+    }
+
+    Timeline::new(edits)
+}
+
+/// Resolves the offset, size, time and sync flag of every sample from the sample table boxes
+fn sample_table(track: &mp4::Mp4Track, timebase: &Timebase, timeline: &Timeline) -> super::Result<Vec<FrameCache>> {
+    let stbl = &track.trak.mdia.minf.stbl;
+    let sample_count = stbl.stsz.sample_count as usize;
+    // Fragmented files leave the sample table empty
+    if sample_count == 0 {
+        return Ok(Vec::new());
+    }
+
+    let chunk_offsets: Vec<u64> = match (&stbl.stco, &stbl.co64) {
+        (Some(stco), _) => stco.entries.iter().map(|offset| *offset as u64).collect(),
+        (None, Some(co64)) => co64.entries.clone(),
+        (None, None) => return Err(super::DemuxError::InvalidData("Missing stco or co64 box".to_string())),
+    };
+
+    let sample_size = |idx: usize| match stbl.stsz.sample_size {
+        0 => stbl.stsz.sample_sizes.get(idx).copied(),
+        size => Some(size),
+    };
+
+    let mut offsets = Vec::with_capacity(sample_count);
+    for (idx, entry) in stbl.stsc.entries.iter().enumerate() {
+        let first_chunk = entry.first_chunk as usize;
+        let last_chunk = stbl.stsc.entries
+            .get(idx + 1)
+            .map(|next| next.first_chunk as usize)
+            .unwrap_or(chunk_offsets.len() + 1);
+
+        for chunk in first_chunk..last_chunk {
+            let mut offset = *chunk_offsets
+                .get(chunk - 1)
+                .ok_or(super::DemuxError::InvalidData(format!("Missing offset for chunk {chunk}")))?;
+
+            for _ in 0..entry.samples_per_chunk {
+                if offsets.len() == sample_count {
+                    break;
+                }
+
+                let size = sample_size(offsets.len())
+                    .ok_or(super::DemuxError::InvalidData(format!("Missing size for sample {}", offsets.len() + 1)))?;
+                offsets.push((offset, size));
+                offset += size as u64;
+            }
+        }
+    }
+
+    let mut times = Vec::with_capacity(sample_count);
+    let mut elapsed = 0u64;
+    for entry in stbl.stts.entries.iter() {
+        for _ in 0..entry.sample_count {
+            times.push((elapsed, entry.sample_delta));
+            elapsed += entry.sample_delta as u64;
+        }
+    }
+
+    // Frames are presented later than they are decoded when B-frames reference them
+    let mut composition_offsets = Vec::with_capacity(sample_count);
+    if let Some(ctts) = &stbl.ctts {
+        for entry in ctts.entries.iter() {
+            composition_offsets.extend((0..entry.sample_count).map(|_| entry.sample_offset));
+        }
+    }
+
+    let mut store = Vec::with_capacity(sample_count);
+    for (idx, (offset, size)) in offsets.into_iter().enumerate() {
+        let (decode_time, duration) = *times
+            .get(idx)
+            .ok_or(super::DemuxError::InvalidData(format!("Missing time for sample {}", idx + 1)))?;
+        let composition_offset = composition_offsets.get(idx).copied().unwrap_or(0);
+        let sample_id = idx as u32 + 1;
+        let keyframe = match &stbl.stss {
+            Some(stss) => stss.entries.binary_search(&sample_id).is_ok(),
+            None => true,
+        };
+
+        store.push(sample_frame(timebase, timeline, offset as usize, size, (decode_time, composition_offset, duration), keyframe));
+    }
+
+    Ok(store)
+}
+
+/// Places a sample on the timeline, `times` are its decode time, composition offset and duration
+/// in the timescale of the track
+fn sample_frame(timebase: &Timebase, timeline: &Timeline, offset: usize, size: u32, times: (u64, i32, u32), keyframe: bool) -> FrameCache {
+    let (decode_time, composition_offset, duration) = times;
+    let presentation_time = (decode_time as i64 + composition_offset as i64).max(0) as u64;
+    let presentation = timeline.map(timebase.to_micros(presentation_time), timebase.duration_to_micros(presentation_time, duration as u64));
+    // The decode time moves along with the presentation time
+    let composition_offset = timebase.to_micros(presentation_time) as i64 - timebase.to_micros(decode_time) as i64;
+
+    FrameCache {
+        decode_timestamp: presentation.timestamp - composition_offset,
+        ..FrameCache::present(offset, size, presentation, keyframe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::video::{mp4::Mp4VideoFile, frames::FrameCacheStore, display::Orientation, test_util::{read_frames, sample_video, fragmented_sample_video}, VideoFile};
+
+    #[test]
+    fn it_reads_the_video_track_of_a_sample_video() {
+        let file = sample_video("video_2s_30fps_1280x720_vp9.mp4", Mp4VideoFile::init);
+
+        assert_eq!(file.codec(), Some("vp09.01.31.08.03.02.02.00.01".to_string()));
+        assert_eq!(file.coded_width().unwrap(), 1280);
+        assert_eq!(file.coded_height().unwrap(), 720);
+        assert_eq!(file.duration().unwrap(), 2.0);
+        assert_eq!(file.display_size(), Some((1280, 720)));
+        assert_eq!(file.pixel_aspect_ratio(), None);
+        assert_eq!(file.orientation(), Orientation::default());
+    }
+
+    #[test]
+    fn it_reads_the_av1_codec_string() {
+        let file = sample_video("video_2s_30fps_640x480_av1.mp4", Mp4VideoFile::init);
+
+        // 4:4:4 sRGB, from the sequence header in the configOBUs
+        assert_eq!(file.codec(), Some("av01.1.04M.08.0.000.01.13.00.1".to_string()));
+        assert_eq!(file.description(), None);
+        assert_eq!(file.color_info().color_space.transfer_name(), Some("iec61966-2-1"));
+    }
+
+    #[test]
+    fn it_reads_all_frames_of_a_sample_video() {
+        let buffer = std::fs::read("../../examples/src/assets/videos/video_5s_60fps_640x480_vp9.mp4").unwrap();
+        let mut file = Mp4VideoFile::open(&buffer).unwrap().unwrap();
+        let store = read_frames(&mut file, &buffer);
+
+        assert_eq!(store.count(), 300);
+        assert!(store.get(0).unwrap().keyframe);
+        assert!(store.get(256).unwrap().keyframe);
+        assert_eq!(store.find_prev_key_frame_before(255), Some(128));
+        assert_eq!(store.get(60).unwrap().timestamp, 1_000_000);
+        assert_eq!(file.frame_rate(), Some(60.0));
+        assert!(store.get(1).unwrap().size > 0);
+    }
+
+    #[test]
+    fn it_resolves_the_same_samples_as_the_mp4_reader() {
+        let buffer: std::rc::Rc<[u8]> = std::fs::read("../../examples/src/assets/videos/video_2s_30fps_640x480_vp9.mp4").unwrap().into();
+        let mut file = Mp4VideoFile::init(buffer.clone()).unwrap();
+        let store = read_frames(&mut file, &buffer);
+
+        for idx in 0..store.count() {
+            let frame = store.get(idx).unwrap();
+            let sample = file.file.read_sample(file.video_track, idx as u32 + 1).unwrap().unwrap();
+            assert_eq!(frame.data(&buffer), Some(&sample.bytes[..]));
+            let timestamp = file.timebase.to_micros((sample.start_time as i64 + sample.rendering_offset as i64) as u64);
+            assert_eq!(frame.timestamp, file.timeline.map(timestamp, 0).timestamp);
+            assert_eq!(frame.keyframe, sample.is_sync);
+        }
+    }
+
+    #[test]
+    fn it_reads_the_frames_of_a_fragmented_file() {
+        let (buffer, fragmented) = fragmented_sample_video("video_5s_60fps_640x480_vp9.mp4", None, 0);
+        let store = read_frames(&mut Mp4VideoFile::open(&buffer).unwrap().unwrap(), &buffer);
+        let fragmented_store = read_frames(&mut Mp4VideoFile::open(&fragmented).unwrap().unwrap(), &fragmented);
+
+        assert_eq!(fragmented_store.count(), 300);
+        for idx in 0..store.count() {
+            let (frame, fragmented_frame) = (store.get(idx).unwrap(), fragmented_store.get(idx).unwrap());
+            assert_eq!(fragmented_frame.data(&fragmented), frame.data(&buffer));
+            assert_eq!(fragmented_frame.timestamp, frame.timestamp);
+            assert_eq!(fragmented_frame.duration, frame.duration);
+            assert_eq!(fragmented_frame.keyframe, frame.keyframe);
+        }
+    }
+
+    #[test]
+    fn it_reads_fragments_once_they_are_complete() {
+        let (_, fragmented) = fragmented_sample_video("video_5s_60fps_640x480_vp9.mp4", None, 0);
+        let mut file = Mp4VideoFile::open(&fragmented).unwrap().unwrap();
+        let mut store = FrameCacheStore::default();
+        let mut counts = Vec::new();
+
+        for end in (0..fragmented.len()).step_by(4096).chain([fragmented.len()]) {
+            file.read_frames(&fragmented[..end], 0, &mut store).unwrap();
+            store.set_loaded(end);
+            counts.push(store.count());
+        }
+
+        assert!(counts.windows(2).all(|counts| counts[0] <= counts[1]));
+        assert!(counts[counts.len() / 2] > 0 && counts[counts.len() / 2] < 300);
+
+        assert_eq!(store.count(), 300);
+        assert_eq!(store.find_prev_key_frame_before(299), Some(256));
     }
 }
@@ -0,0 +1,53 @@
+use super::frames::FrameCacheStore;
+
+/// What a decoder has been fed since it was configured, reset or flushed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecoderState {
+    /// The next frame has to be a keyframe
+    #[default]
+    Reset,
+    /// The last frame it was fed, in decode order
+    Decoded(usize),
+}
+
+/// The frames to feed a decoder in `state`, in decode order, so it outputs the frames presented as
+/// `from` up to and including `to`. Decoding continues from the last frame when no keyframe comes
+/// in between, otherwise it starts at the keyframe before the first frame.
+pub fn plan(frames: &FrameCacheStore, from: usize, to: usize, state: DecoderState) -> Vec<usize> {
+    let (first, last) = match frames.decode_range(from, to) {
+        Some(range) => range,
+        None => return Vec::new(),
+    };
+    let keyframe = frames.find_prev_key_frame_before(first).unwrap_or(0);
+
+    let start = match state {
+        DecoderState::Decoded(current) if current <= first && keyframe <= current => current + 1,
+        _ => keyframe,
+    };
+
+    (start..=last).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan, DecoderState};
+    use crate::video::frames::{FrameCache, FrameCacheStore};
+
+    #[test]
+    fn it_plans_from_the_decoder_state() {
+        let mut store = FrameCacheStore::default();
+        for idx in 0..8 {
+            store.push(FrameCache::new(idx * 5, 5, idx as i64 * 10, Some(10), idx % 4 == 0));
+        }
+        store.set_loaded(40);
+
+        assert_eq!(plan(&store, 2, 2, DecoderState::Reset), vec![0, 1, 2]);
+        assert_eq!(plan(&store, 3, 5, DecoderState::Decoded(2)), vec![3, 4, 5]);
+        // The frame has been output already
+        assert!(plan(&store, 2, 2, DecoderState::Decoded(2)).is_empty());
+        // Going back, or past the next keyframe, starts over
+        assert_eq!(plan(&store, 1, 1, DecoderState::Decoded(2)), vec![0, 1]);
+        assert_eq!(plan(&store, 6, 6, DecoderState::Decoded(2)), vec![4, 5, 6]);
+        assert!(plan(&store, 9, 9, DecoderState::Reset).is_empty());
+    }
+}
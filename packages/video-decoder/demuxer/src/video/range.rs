@@ -0,0 +1,385 @@
+use super::{
+    VideoFile, DemuxError,
+    frames::FrameCacheStore,
+    format::Format,
+    mkv::MkvVideoFile,
+    mp4::Mp4VideoFile,
+    ebml, isobmff,
+};
+
+/// Reading failed, either because the bytes haven't been fetched yet or because they are invalid.
+#[derive(Debug)]
+pub enum RangeError {
+    Missing(usize, usize),
+    Demux(DemuxError),
+}
+
+impl From<DemuxError> for RangeError {
+    fn from(value: DemuxError) -> Self {
+        RangeError::Demux(value)
+    }
+}
+
+impl From<String> for RangeError {
+    fn from(value: String) -> Self {
+        RangeError::Demux(DemuxError::InvalidData(value))
+    }
+}
+
+impl From<&str> for RangeError {
+    fn from(value: &str) -> Self {
+        value.to_string().into()
+    }
+}
+
+/// The parts of a file that have been fetched, adjacent and overlapping parts are merged.
+pub struct RangeBuffer {
+    size: usize,
+    ranges: Vec<(usize, Vec<u8>)>,
+}
+
+impl RangeBuffer {
+    pub fn new(size: usize) -> Self {
+        Self { size, ranges: Vec::new() }
+    }
+
+    /// The size of the complete file
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn insert(&mut self, offset: usize, bytes: &[u8]) {
+        let mut start = offset;
+        let mut end = offset + bytes.len();
+        let (touching, mut ranges): (Vec<_>, Vec<_>) = std::mem::take(&mut self.ranges)
+            .into_iter()
+            .partition(|(range_start, range)| *range_start <= end && range_start + range.len() >= start);
+
+        for (range_start, range) in touching.iter() {
+            start = start.min(*range_start);
+            end = end.max(range_start + range.len());
+        }
+
+        let mut merged = vec![0; end - start];
+        for (range_start, range) in touching.iter() {
+            merged[range_start - start..range_start - start + range.len()].copy_from_slice(range);
+        }
+        merged[offset - start..offset - start + bytes.len()].copy_from_slice(bytes);
+
+        ranges.push((start, merged));
+        ranges.sort_by_key(|(range_start, _)| *range_start);
+        self.ranges = ranges;
+    }
+
+    /// Returns `size` bytes at `offset`, or less at the end of the file
+    pub fn get(&self, offset: usize, size: usize) -> Result<&[u8], RangeError> {
+        if offset >= self.size {
+            return Err(format!("Cannot read at {offset}, the file is only {} bytes", self.size).into());
+        }
+
+        let end = (offset + size).min(self.size);
+        self.ranges
+            .iter()
+            .find(|(start, range)| *start <= offset && start + range.len() >= end)
+            .map(|(start, range)| &range[offset - start..end - start])
+            .ok_or(RangeError::Missing(offset, end - offset))
+    }
+
+    /// The number of bytes that have been fetched
+    pub fn fetched(&self) -> usize {
+        self.ranges.iter().map(|(_, range)| range.len()).sum()
+    }
+}
+
+/// Headers are small but scattered, reading a bit more at once saves a lot of round trips
+const MIN_HEADER_READ: usize = 64 * 1024;
+
+/// A file that is read through byte ranges instead of being loaded completely.
+///
+/// Fetching the bytes is left to the caller: `header_read` and `frames_read` return the range that
+/// is needed next, which is added with `insert`, until they return `None`. Only the `moov` box or
+/// the Matroska SeekHead, Info & Tracks are read up front, Matroska clusters and the `moof` boxes of
+/// fragmented MP4 files are indexed one by one when their frames are needed. Only the first keyframe
+/// of files whose codec configuration is incomplete is read up front.
+pub struct RangeFile {
+    format: Format,
+    ranges: RangeBuffer,
+    file: Option<Box<dyn VideoFile>>,
+    frames: FrameCacheStore,
+    // The next cluster or top level box after the `moov` box to index
+    next_fragment: Option<usize>,
+}
+
+impl RangeFile {
+    pub fn new(format: Format, size: usize) -> super::Result<Self> {
+        match format {
+            Format::Matroska | Format::IsoBmff => Ok(Self {
+                format,
+                ranges: RangeBuffer::new(size),
+                file: None,
+                frames: FrameCacheStore::default(),
+                next_fragment: None,
+            }),
+            format => Err(DemuxError::InvalidData(format!("{format} files are not supported"))),
+        }
+    }
+
+    pub fn insert(&mut self, offset: usize, bytes: &[u8]) {
+        self.ranges.insert(offset, bytes);
+    }
+
+    /// The range that has to be fetched next to read the headers, `None` once they have been read
+    pub fn header_read(&mut self) -> super::Result<Option<(usize, usize)>> {
+        if self.file.is_some() && !self.file.as_ref().is_some_and(|file| file.needs_keyframe()) {
+            return Ok(None);
+        }
+
+        match self.open().and_then(|()| self.read_first_keyframe()) {
+            Ok(()) => Ok(None),
+            Err(RangeError::Missing(offset, size)) => {
+                let size = size.max(MIN_HEADER_READ).min(self.ranges.size() - offset);
+                Ok(Some((offset, size)))
+            },
+            Err(RangeError::Demux(err)) => Err(err),
+        }
+    }
+
+    /// The range that has to be fetched next to decode the frames presented as `from` up to and
+    /// including `to`, `None` once all of them can be decoded
+    pub fn frames_read(&mut self, from: usize, to: usize) -> super::Result<Option<(usize, usize)>> {
+        match self.load_frames(from, to) {
+            Ok(()) => Ok(None),
+            Err(RangeError::Missing(offset, size)) => Ok(Some((offset, size))),
+            Err(RangeError::Demux(err)) => Err(err),
+        }
+    }
+
+    /// The video file, once its headers have been read
+    pub fn file(&self) -> Option<&dyn VideoFile> {
+        self.file.as_deref()
+    }
+
+    /// The frames that are known so far, this grows with every indexed cluster or fragment
+    pub fn frames(&self) -> &FrameCacheStore {
+        &self.frames
+    }
+
+    pub fn ranges(&self) -> &RangeBuffer {
+        &self.ranges
+    }
+
+    fn open(&mut self) -> Result<(), RangeError> {
+        if self.file.is_some() {
+            return Ok(());
+        }
+
+        let mut file: Box<dyn VideoFile> = match self.format {
+            Format::Matroska => {
+                let (header, first_cluster) = ebml::find_header(&self.ranges)?;
+                self.next_fragment = Some(first_cluster);
+                Box::new(MkvVideoFile::init(header.into())?)
+            },
+            _ => {
+                let (header, moov_end) = isobmff::find_header(&self.ranges)?;
+                self.next_fragment = Some(moov_end).filter(|end| *end < self.ranges.size());
+                Box::new(Mp4VideoFile::init(header.into())?)
+            },
+        };
+
+        // This gets all MP4 samples from the sample table, fragments are read like clusters
+        file.read_frames(&[], 0, &mut self.frames)?;
+        self.frames.set_loaded(self.ranges.size());
+        self.file = Some(file);
+
+        Ok(())
+    }
+
+    /// Reads the codec parameters the configuration lacks from the first keyframe, which indexes
+    /// the first cluster or fragment when the frames aren't known yet
+    fn read_first_keyframe(&mut self) -> Result<(), RangeError> {
+        if !self.file.as_ref().is_some_and(|file| file.needs_keyframe()) {
+            return Ok(());
+        }
+
+        self.load_frames(0, 0)?;
+
+        let frames = &self.frames;
+        let keyframe = (0..frames.count()).filter_map(|idx| frames.get(idx)).find(|frame| frame.keyframe);
+        if let (Some(file), Some(keyframe)) = (self.file.as_mut(), keyframe) {
+            file.read_keyframe(self.ranges.get(keyframe.offset, keyframe.size as usize)?);
+        }
+
+        Ok(())
+    }
+
+    fn load_frames(&mut self, from: usize, to: usize) -> Result<(), RangeError> {
+        let file = self.file.as_mut().ok_or("The headers have not been read yet")?;
+        let size = self.ranges.size();
+
+        // `to` is a presentation index, decode-only frames are indexed but never presented
+        while self.frames.presentation_count() <= to {
+            let position = match self.next_fragment {
+                Some(position) => position,
+                None => break,
+            };
+            // Only the `moof` boxes are fetched, not the media data in between
+            let (indexed, end) = match self.format {
+                Format::Matroska => (true, ebml::cluster_end(&self.ranges, position)?),
+                _ => {
+                    let (name, end) = isobmff::box_at(&self.ranges, position)?;
+                    (&name == b"moof", end)
+                },
+            };
+
+            if indexed {
+                file.read_frames(self.ranges.get(position, end - position)?, position, &mut self.frames)?;
+                self.frames.set_loaded(size);
+            }
+            self.next_fragment = Some(end).filter(|end| *end < size);
+        }
+
+        // The frames that aren't fetched yet are fetched at once, frames are mostly stored in order
+        let (frames, ranges) = (&self.frames, &self.ranges);
+        let (from, to) = match frames.decode_range(from, to) {
+            Some(range) => range,
+            // The frames are in fragments that haven't been indexed
+            None => return match self.next_fragment {
+                Some(position) => Err(RangeError::Missing(position, 1)),
+                None => Ok(()),
+            },
+        };
+        let missing = (from..=to)
+            .filter_map(|idx| frames.get(idx))
+            .filter(|frame| ranges.get(frame.offset, frame.size as usize).is_err())
+            .fold(None, |missing: Option<(usize, usize)>, frame| {
+                let end = frame.offset + frame.size as usize;
+                Some(match missing {
+                    Some((start, missing_end)) => (start.min(frame.offset), missing_end.max(end)),
+                    None => (frame.offset, end),
+                })
+            });
+
+        match missing {
+            Some((start, end)) => Err(RangeError::Missing(start, end - start)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::video::{range::{RangeBuffer, RangeFile}, format::Format, test_util::{strip_config_obus, fragmented_sample_video}};
+
+    fn open_range_file(buffer: &[u8], format: Format) -> RangeFile {
+        let mut file = RangeFile::new(format, buffer.len()).unwrap();
+
+        while let Some((offset, size)) = file.header_read().unwrap() {
+            file.insert(offset, &buffer[offset..offset + size]);
+        }
+
+        file
+    }
+
+    fn fetch_frames(file: &mut RangeFile, buffer: &[u8], from: usize, to: usize) {
+        while let Some((offset, size)) = file.frames_read(from, to).unwrap() {
+            file.insert(offset, &buffer[offset..offset + size]);
+        }
+    }
+
+    #[test]
+    fn it_merges_ranges() {
+        let mut ranges = RangeBuffer::new(10);
+        ranges.insert(4, &[4, 5]);
+        ranges.insert(0, &[0, 1]);
+        assert!(ranges.get(0, 6).is_err());
+
+        ranges.insert(1, &[1, 2, 3]);
+        assert_eq!(ranges.get(0, 6).unwrap(), &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(ranges.fetched(), 6);
+        assert!(ranges.get(8, 4).is_err());
+    }
+
+    #[test]
+    fn it_rejects_malformed_top_level_boxes() {
+        // A box with a 64 bit size that runs past the end of memory
+        let mut buffer = vec![0, 0, 0, 1];
+        buffer.extend_from_slice(b"free");
+        buffer.extend_from_slice(&u64::MAX.to_be_bytes());
+        buffer.extend_from_slice(&[0; 16]);
+
+        let mut file = RangeFile::new(Format::IsoBmff, buffer.len()).unwrap();
+        file.insert(0, &buffer);
+        assert!(file.header_read().is_err());
+
+        // A box smaller than its header
+        buffer[..4].copy_from_slice(&[0, 0, 0, 4]);
+        let mut file = RangeFile::new(Format::IsoBmff, buffer.len()).unwrap();
+        file.insert(0, &buffer);
+        assert!(file.header_read().is_err());
+    }
+
+    #[test]
+    fn it_reads_mp4_frames_on_demand() {
+        let buffer = std::fs::read("../../examples/src/assets/videos/video_5s_30fps_1920x1080_vp9.mp4").unwrap();
+        let mut file = open_range_file(&buffer, Format::IsoBmff);
+
+        assert_eq!(file.frames().count(), 150);
+        assert_eq!(file.file().unwrap().coded_width().unwrap(), 1920);
+
+        fetch_frames(&mut file, &buffer, 0, 10);
+        let frame = file.frames().get(10).unwrap();
+        assert_eq!(file.ranges().get(frame.offset, frame.size as usize).unwrap(), frame.data(&buffer).unwrap());
+        assert!(file.ranges().get(file.frames().get(140).unwrap().offset, 1).is_err());
+        assert!(file.ranges().fetched() < buffer.len() / 2);
+    }
+
+    #[test]
+    fn it_fetches_the_first_keyframe_without_a_sequence_header() {
+        let mut buffer = std::fs::read("../../examples/src/assets/videos/video_2s_30fps_640x480_av1.mp4").unwrap();
+        strip_config_obus(&mut buffer);
+
+        let file = open_range_file(&buffer, Format::IsoBmff);
+        let keyframe = file.frames().get(0).unwrap();
+        assert!(file.ranges().get(keyframe.offset, keyframe.size as usize).is_ok());
+        assert!(file.file().unwrap().codec().unwrap().starts_with("av01.1.") && !file.file().unwrap().needs_keyframe());
+    }
+
+    #[test]
+    fn it_indexes_fragments_until_the_presented_frames_are_known() {
+        // Fragments of 10 frames that split the groups of pictures, the first 2 frames are only
+        // decoded
+        let (_, buffer) = fragmented_sample_video("video_5s_60fps_640x480_vp9.mp4", Some(10), 2);
+        let mut file = open_range_file(&buffer, Format::IsoBmff);
+
+        fetch_frames(&mut file, &buffer, 0, 9);
+        assert_eq!((file.frames().count(), file.frames().presentation_count()), (20, 18));
+        for idx in 0..=9 {
+            let frame = file.frames().get(file.frames().presentation_to_decode(idx).unwrap()).unwrap();
+            assert!(file.ranges().get(frame.offset, frame.size as usize).is_ok());
+        }
+
+        fetch_frames(&mut file, &buffer, 290, 400);
+        assert_eq!(file.frames().presentation_count(), 298);
+    }
+
+    #[test]
+    fn it_indexes_matroska_clusters_on_demand() {
+        let buffer = std::fs::read("../../examples/src/assets/videos/video_5s_30fps_1920x1080_vp9.webm").unwrap();
+        let mut file = open_range_file(&buffer, Format::Matroska);
+
+        // The file has no CodecPrivate, so the first cluster is read to get the codec from its first
+        // keyframe. It holds the first group of pictures.
+        assert_eq!(file.frames().count(), 128);
+        assert_eq!(file.file().unwrap().coded_height().unwrap(), 1080);
+        assert!(file.file().unwrap().codec().is_some());
+
+        fetch_frames(&mut file, &buffer, 0, 10);
+        let frame = file.frames().get(10).unwrap();
+        assert_eq!(file.ranges().get(frame.offset, frame.size as usize).unwrap(), frame.data(&buffer).unwrap());
+        assert_eq!(file.frames().count(), 128);
+        assert!(file.ranges().fetched() < buffer.len());
+
+        fetch_frames(&mut file, &buffer, 140, 149);
+        assert_eq!(file.frames().count(), 150);
+    }
+}
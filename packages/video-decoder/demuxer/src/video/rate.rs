@@ -0,0 +1,76 @@
+use super::frames::FrameCacheStore;
+
+/// Matroska timestamps are usually in milliseconds, so the intervals of constant frame rate video
+/// differ by up to a millisecond (33ms & 34ms at 30fps)
+const INTERVAL_TOLERANCE: u64 = 1000;
+
+/// The frame rate of a video, intervals are in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameRate {
+    /// The declared rate when the frames match it, the measured rate otherwise
+    pub rate: f64,
+    /// The rate the container declares
+    pub declared: Option<f64>,
+    /// Whether the intervals between the frames differ
+    pub variable: bool,
+    pub min_interval: u64,
+    pub max_interval: u64,
+    pub mean_interval: f64,
+}
+
+impl FrameRate {
+    /// Measures the intervals between the presented frames, `None` with less than two frames
+    pub fn measure(frames: &FrameCacheStore, declared: Option<f64>) -> Option<Self> {
+        let timestamps: Vec<i64> = frames.table().iter().map(|frame| frame.timestamp).collect();
+        let intervals: Vec<u64> = timestamps.windows(2).map(|pair| (pair[1] - pair[0]) as u64).collect();
+
+        let min_interval = *intervals.iter().min()?;
+        let max_interval = *intervals.iter().max()?;
+        let mean_interval = (timestamps[timestamps.len() - 1] - timestamps[0]) as f64 / intervals.len() as f64;
+
+        let measured = 1_000_000.0 / mean_interval;
+        let rate = match declared {
+            Some(declared) if (1_000_000.0 / declared - mean_interval).abs() <= INTERVAL_TOLERANCE as f64 => declared,
+            _ => measured,
+        };
+
+        Some(Self {
+            rate,
+            declared,
+            variable: max_interval - min_interval > INTERVAL_TOLERANCE,
+            min_interval,
+            max_interval,
+            mean_interval,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameRate;
+    use crate::video::frames::{FrameCache, FrameCacheStore};
+
+    fn store(timestamps: &[i64]) -> FrameCacheStore {
+        let mut store = FrameCacheStore::default();
+        for (idx, timestamp) in timestamps.iter().enumerate() {
+            store.push(FrameCache::new(idx, 1, *timestamp, None, idx == 0));
+        }
+        store.set_loaded(timestamps.len());
+        store
+    }
+
+    #[test]
+    fn it_measures_the_frame_rate() {
+        let constant = FrameRate::measure(&store(&[0, 33000, 67000, 100000]), Some(30.0)).unwrap();
+        assert_eq!(constant.rate, 30.0);
+        assert!(!constant.variable);
+        assert_eq!((constant.min_interval, constant.max_interval), (33000, 34000));
+
+        let variable = FrameRate::measure(&store(&[0, 40000, 60000, 80000, 120000]), Some(25.0)).unwrap();
+        assert!(variable.variable);
+        assert_eq!(variable.mean_interval, 30000.0);
+        assert_eq!((variable.rate * 1000.0).round(), 33333.0);
+
+        assert_eq!(FrameRate::measure(&store(&[0]), None), None);
+    }
+}
@@ -0,0 +1,192 @@
+use super::{
+    VideoFile, DemuxError,
+    frames::FrameCacheStore,
+    format::{self, Format},
+    mkv::MkvVideoFile,
+    mp4::Mp4VideoFile,
+};
+
+/// Demuxes a file while it is being loaded. The headers are parsed as soon as they are complete
+/// and the frame store grows with every complete cluster or sample.
+pub struct VideoStream {
+    buffer: Vec<u8>,
+    format: Option<Format>,
+    file: Option<Box<dyn VideoFile>>,
+    frames: FrameCacheStore,
+    ended: bool,
+}
+
+impl VideoStream {
+    /// `format` is detected from the first bytes when it isn't known up front
+    pub fn new(format: Option<Format>) -> Self {
+        Self {
+            buffer: Vec::new(),
+            format,
+            file: None,
+            frames: FrameCacheStore::default(),
+            ended: false,
+        }
+    }
+
+    pub fn append(&mut self, bytes: &[u8]) -> super::Result<()> {
+        if self.ended {
+            return Err(DemuxError::InvalidData("Cannot append data after the end of the stream".to_string()));
+        }
+
+        self.buffer.extend_from_slice(bytes);
+        self.update()
+    }
+
+    /// Checks the file is complete, no more data can be appended afterwards
+    pub fn end_of_stream(&mut self) -> super::Result<()> {
+        self.ended = true;
+        self.update()?;
+
+        if self.file.is_none() {
+            return Err(DemuxError::InvalidData("The stream ended before the headers were complete".to_string()));
+        }
+
+        if self.frames.count() == 0 {
+            return Err(DemuxError::NoFrames("The stream does not contain any frames".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// The video file, once its headers have been loaded
+    pub fn file(&self) -> Option<&dyn VideoFile> {
+        self.file.as_deref()
+    }
+
+    pub fn frames(&self) -> &FrameCacheStore {
+        &self.frames
+    }
+
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    pub fn ended(&self) -> bool {
+        self.ended
+    }
+
+    fn update(&mut self) -> super::Result<()> {
+        if self.format.is_none() {
+            self.format = match format::detect(&self.buffer) {
+                Ok(format) => Some(format),
+                // A few more bytes might still make the format recognizable
+                Err(_) if !self.ended && self.buffer.len() < format::SNIFF_SIZE => return Ok(()),
+                Err(err) => return Err(err),
+            };
+        }
+
+        if self.file.is_none() {
+            self.file = match self.format {
+                Some(Format::Matroska) => MkvVideoFile::open(&self.buffer)?.map(|file| Box::new(file) as Box<dyn VideoFile>),
+                Some(Format::IsoBmff) => Mp4VideoFile::open(&self.buffer)?.map(|file| Box::new(file) as Box<dyn VideoFile>),
+                Some(format) => return Err(DemuxError::InvalidData(format!("{format} files are not supported"))),
+                None => None,
+            };
+        }
+
+        if let Some(file) = self.file.as_mut() {
+            file.read_frames(&self.buffer, 0, &mut self.frames)?;
+            self.frames.set_loaded(self.buffer.len());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::video::{stream::VideoStream, format::Format, mkv::MkvVideoFile, test_util::{read_frames, sample_video, strip_config_obus}, VideoFile};
+
+    fn stream_sample_video(name: &str, format: Option<Format>, chunk_size: usize) -> (VideoStream, Vec<usize>) {
+        let buffer = std::fs::read(format!("../../examples/src/assets/videos/{name}")).unwrap();
+        let mut stream = VideoStream::new(format);
+        let mut counts = Vec::new();
+
+        for chunk in buffer.chunks(chunk_size) {
+            stream.append(chunk).unwrap();
+            counts.push(stream.frames().count());
+        }
+        stream.end_of_stream().unwrap();
+
+        (stream, counts)
+    }
+
+    #[test]
+    fn it_grows_the_frames_while_a_matroska_file_loads() {
+        let (stream, counts) = stream_sample_video("video_5s_30fps_640x480_vp9.mkv", None, 4096);
+
+        assert!(counts.windows(2).all(|counts| counts[0] <= counts[1]));
+        assert!(counts[counts.len() / 2] > 0 && counts[counts.len() / 2] < 150);
+        assert_eq!(stream.frames().count(), 150);
+        assert_eq!(stream.file().unwrap().coded_width().unwrap(), 640);
+    }
+
+    #[test]
+    fn it_grows_the_frames_while_an_mp4_file_loads() {
+        let (stream, counts) = stream_sample_video("video_5s_30fps_640x480_vp9.mp4", Some(Format::IsoBmff), 4096);
+
+        assert!(counts.windows(2).all(|counts| counts[0] <= counts[1]));
+        assert_eq!(stream.frames().count(), 150);
+        assert_eq!(stream.file().unwrap().codec(), Some("vp09.01.30.08.03.02.02.00.01".to_string()));
+    }
+
+    #[test]
+    fn it_reads_the_av1_sequence_header_from_the_first_keyframe() {
+        // The moov box of the MP4 file follows the media data, so its keyframe is there at once
+        for (name, waits) in [("video_2s_30fps_640x480_av1.webm", true), ("video_2s_30fps_640x480_av1.mp4", false)] {
+            let mut buffer = std::fs::read(format!("../../examples/src/assets/videos/{name}")).unwrap();
+            strip_config_obus(&mut buffer);
+
+            let mut stream = VideoStream::new(None);
+            let mut waited = false;
+            for chunk in buffer.chunks(512) {
+                stream.append(chunk).unwrap();
+                waited |= stream.file().is_some_and(|file| file.needs_keyframe());
+            }
+            stream.end_of_stream().unwrap();
+
+            let file = stream.file().unwrap();
+            assert_eq!(waited, waits);
+            assert!(!file.needs_keyframe());
+            assert_eq!(file.codec(), Some("av01.1.04M.08.0.000.01.13.00.1".to_string()));
+        }
+    }
+
+    #[test]
+    fn it_estimates_the_same_vp9_level_as_a_loaded_file() {
+        // The bitrate of the frames read so far crosses a level limit in these samples
+        for name in ["video_5s_60fps_640x480_vp9.mkv", "video_2s_60fps_640x480_vp9.webm"] {
+            let mut file = sample_video(name, MkvVideoFile::init);
+            let buffer = std::fs::read(format!("../../examples/src/assets/videos/{name}")).unwrap();
+            read_frames(&mut file, &buffer);
+            let codec = file.codec();
+            assert!(codec.is_some());
+
+            let mut stream = VideoStream::new(None);
+            for chunk in buffer.chunks(512) {
+                stream.append(chunk).unwrap();
+                if let Some(file) = stream.file().filter(|file| !file.needs_keyframe()) {
+                    assert_eq!(file.codec(), codec);
+                }
+            }
+            stream.end_of_stream().unwrap();
+            assert_eq!(stream.file().unwrap().codec(), codec);
+        }
+    }
+
+    #[test]
+    fn it_fails_when_the_headers_are_incomplete() {
+        let buffer = std::fs::read("../../examples/src/assets/videos/video_2s_30fps_640x480_vp8.webm").unwrap();
+        let mut stream = VideoStream::new(None);
+
+        stream.append(&buffer[..64]).unwrap();
+        assert!(stream.file().is_none());
+        assert!(stream.end_of_stream().is_err());
+        assert!(stream.append(&buffer[64..]).is_err());
+    }
+}
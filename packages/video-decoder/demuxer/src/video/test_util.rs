@@ -0,0 +1,106 @@
+// Helpers shared by the tests of the containers
+
+use std::{io::Cursor, rc::Rc};
+
+use mp4::WriteBox;
+
+use super::{VideoFile, frames::FrameCacheStore};
+
+/// Reads all frames of a completely loaded file
+pub fn read_frames(file: &mut dyn VideoFile, buffer: &[u8]) -> FrameCacheStore {
+    let mut store = FrameCacheStore::default();
+    file.read_frames(buffer, 0, &mut store).unwrap();
+    store.set_loaded(buffer.len());
+    store
+}
+
+/// Opens one of the videos of the examples with the `init` of a container
+pub fn sample_video<T>(name: &str, init: impl Fn(Rc<[u8]>) -> super::Result<T>) -> T {
+    let buffer = std::fs::read(format!("../../examples/src/assets/videos/{name}")).unwrap();
+    init(buffer.into()).unwrap()
+}
+
+/// Turns the configOBUs after the av1C record of the 640x480 AV1 samples into a padding OBU, so
+/// the sequence header is only in the first keyframe
+pub fn strip_config_obus(buffer: &mut [u8]) {
+    let config = buffer.windows(6).position(|bytes| bytes == [0x81, 0x24, 0x00, 0x00, 0x0A, 0x0D]).unwrap() + 4;
+    buffer[config..config + 15].copy_from_slice(&[[0x7A, 0x0D].as_slice(), &[0; 13]].concat());
+}
+
+fn mp4_box(name: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut data = (body.len() as u32 + 8).to_be_bytes().to_vec();
+    data.extend_from_slice(name);
+    data.extend_from_slice(body);
+    data
+}
+
+fn fragment(track_id: u32, sequence: u32, samples: &[mp4::Mp4Sample]) -> Vec<u8> {
+    let moof = |data_offset: u32| {
+        let mfhd = [&[0; 4][..], &sequence.to_be_bytes()].concat();
+        // default-base-is-moof, the durations & flags come from trex
+        let tfhd = [&0x020000u32.to_be_bytes()[..], &track_id.to_be_bytes()].concat();
+        let tfdt = [&[1, 0, 0, 0][..], &samples[0].start_time.to_be_bytes()].concat();
+        // data-offset, first-sample-flags & sample-size present
+        let first_sample_flags = if samples[0].is_sync { 0 } else { 0x10000 };
+        let mut trun = [0x205u32, samples.len() as u32, data_offset, first_sample_flags].iter().flat_map(|value| value.to_be_bytes()).collect::<Vec<u8>>();
+        trun.extend(samples.iter().flat_map(|sample| sample.bytes.len().to_be_bytes()[4..].to_vec()));
+
+        let traf = [mp4_box(b"tfhd", &tfhd), mp4_box(b"tfdt", &tfdt), mp4_box(b"trun", &trun)].concat();
+        [mp4_box(b"mfhd", &mfhd), mp4_box(b"traf", &traf)].concat()
+    };
+    let moof_size = mp4_box(b"moof", &moof(0)).len() as u32;
+    let mdat: Vec<u8> = samples.iter().flat_map(|sample| sample.bytes.to_vec()).collect();
+
+    [mp4_box(b"moof", &moof(moof_size + 8)), mp4_box(b"mdat", &mdat)].concat()
+}
+
+/// Rewrites an MP4 sample video to a fragmented file, with a fragment for every keyframe or for
+/// every `fragment_length` samples. The edit list of the result skips the first `skipped` samples.
+pub fn fragmented_sample_video(name: &str, fragment_length: Option<usize>, skipped: u32) -> (Vec<u8>, Vec<u8>) {
+    let buffer = std::fs::read(format!("../../examples/src/assets/videos/{name}")).unwrap();
+    let mut reader = mp4::Mp4Reader::read_header(Cursor::new(buffer.clone()), buffer.len() as u64).unwrap();
+    let track_id = *reader.tracks().keys().next().unwrap();
+    let samples: Vec<mp4::Mp4Sample> = (1..=reader.sample_count(track_id).unwrap())
+        .map(|id| reader.read_sample(track_id, id).unwrap().unwrap())
+        .collect();
+
+    let mut moov = reader.moov.clone();
+    let (movie_timescale, trak) = (moov.mvhd.timescale as u64, &mut moov.traks[0]);
+    let stbl = &mut trak.mdia.minf.stbl;
+    stbl.stts.entries.clear();
+    stbl.stsc.entries.clear();
+    stbl.stsz.sample_count = 0;
+    stbl.stsz.sample_sizes.clear();
+    stbl.stss = None;
+    stbl.stco.as_mut().unwrap().entries.clear();
+
+    if skipped > 0 {
+        let skipped = skipped as u64 * samples[1].duration as u64;
+        let edit = &mut trak.edts.as_mut().and_then(|edts| edts.elst.as_mut()).unwrap().entries[0];
+        edit.media_time += skipped;
+        edit.segment_duration -= skipped * movie_timescale / trak.mdia.mdhd.timescale as u64;
+    }
+
+    let mut file = Vec::new();
+    reader.ftyp.write_box(&mut file).unwrap();
+    let mut moov_body = Vec::new();
+    moov.write_box(&mut moov_body).unwrap();
+    // The mp4 crate doesn't write mvex, trex makes every sample a non-sync sample of the same length
+    let trex = [0, track_id, 1, samples[1].duration, 0, 0x10000].iter().flat_map(|value| value.to_be_bytes()).collect::<Vec<u8>>();
+    moov_body.extend(mp4_box(b"mvex", &mp4_box(b"trex", &trex)));
+    file.extend(mp4_box(b"moov", &moov_body[8..]));
+
+    let mut start = 0;
+    for end in 1..=samples.len() {
+        let split = match fragment_length {
+            Some(length) => end - start == length,
+            None => samples[end.min(samples.len() - 1)].is_sync,
+        };
+        if end == samples.len() || split {
+            file.extend(fragment(track_id, start as u32 + 1, &samples[start..end]));
+            start = end;
+        }
+    }
+
+    (buffer, file)
+}
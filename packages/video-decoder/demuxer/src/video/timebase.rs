@@ -0,0 +1,76 @@
+/// The unit of the timestamps in a container, as a fraction of a second. Timestamps are converted
+/// one by one from their absolute value, so rounding never adds up over the length of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timebase {
+    num: u64,
+    den: u64,
+}
+
+const MICROS_PER_SECOND: u64 = 1_000_000;
+
+pub(super) fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
+
+impl Timebase {
+    /// A tick of `num / den` seconds, a denominator of 0 is treated as 1
+    pub fn new(num: u64, den: u64) -> Self {
+        let den = den.max(1);
+        let divisor = gcd(num, den).max(1);
+
+        Self { num: num / divisor, den: den / divisor }
+    }
+
+    /// MP4 tracks count `timescale` ticks per second
+    pub fn from_timescale(timescale: u32) -> Self {
+        Self::new(1, timescale as u64)
+    }
+
+    /// Matroska ticks are `scale` nanoseconds (the TimestampScale)
+    pub fn from_nanoseconds(scale: u64) -> Self {
+        Self::new(scale, 1_000_000_000)
+    }
+
+    /// Converts ticks to microseconds, rounded to the nearest microsecond
+    pub fn to_micros(&self, ticks: u64) -> u64 {
+        let den = self.den as u128;
+        let micros = (ticks as u128 * self.num as u128 * MICROS_PER_SECOND as u128 + den / 2) / den;
+
+        micros as u64
+    }
+
+    /// Converts the duration of something starting at `start` to microseconds, so that it ends
+    /// exactly where the next thing starts
+    pub fn duration_to_micros(&self, start: u64, duration: u64) -> u64 {
+        self.to_micros(start + duration) - self.to_micros(start)
+    }
+
+    pub fn to_seconds(&self, ticks: f64) -> f64 {
+        ticks * self.num as f64 / self.den as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timebase;
+
+    #[test]
+    fn it_converts_ntsc_timestamps_without_drift() {
+        // 29.97 & 59.94 fps, a frame every 1001 ticks
+        let ntsc = Timebase::from_timescale(30000);
+        assert_eq!(ntsc.to_micros(1001), 33367);
+        assert_eq!(ntsc.to_micros(1001 * 30000), 1_001_000_000);
+        let durations: u64 = (0..30000).map(|frame| ntsc.duration_to_micros(frame * 1001, 1001)).sum();
+        assert_eq!(durations, 1_001_000_000);
+
+        let double_ntsc = Timebase::from_timescale(60000);
+        assert_eq!(double_ntsc.to_micros(1001 * 60000 * 60), 60_060_000_000);
+
+        assert_eq!(Timebase::from_nanoseconds(1_000_000), Timebase::new(1, 1000));
+        assert_eq!(Timebase::from_nanoseconds(1_000_000).to_micros(2002), 2_002_000);
+        assert_eq!(Timebase::from_nanoseconds(1_000_000).to_seconds(2000.0), 2.0);
+    }
+}
@@ -0,0 +1,112 @@
+/// A part of the presentation timeline, like an MP4 edit list entry. Times are in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edit {
+    /// Where the edit starts on the presentation timeline
+    pub start: u64,
+    /// `None` to last until the end of the media
+    pub duration: Option<u64>,
+    /// Where the edit starts in the media, `None` for an empty edit that presents nothing
+    pub media_time: Option<u64>,
+    /// 0 shows the frame at `media_time` for the whole edit
+    pub rate: f64,
+}
+
+/// When a frame is presented, frames outside of the edits are only decoded because other frames
+/// depend on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Presentation {
+    /// Negative for decode-only frames before the start of the timeline
+    pub timestamp: i64,
+    pub duration: u64,
+    pub decode_only: bool,
+}
+
+/// Maps the timestamps in the media to the presentation timeline. Without edits every frame is
+/// presented at its own timestamp.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timeline {
+    edits: Vec<Edit>,
+}
+
+impl Timeline {
+    pub fn new(edits: Vec<Edit>) -> Self {
+        Self { edits }
+    }
+
+    /// Presents the media `delay` microseconds earlier, like the Matroska CodecDelay
+    pub fn shift(delay: u64) -> Self {
+        Self::new(vec![Edit { start: 0, duration: None, media_time: Some(delay), rate: 1.0 }])
+    }
+
+    /// Where the last edit ends, `None` without edits or when one lasts until the end of the media
+    pub fn duration(&self) -> Option<u64> {
+        self.edits.iter().try_fold(0, |end: u64, edit| Some(end.max(edit.start + edit.duration?)))
+            .filter(|_| !self.edits.is_empty())
+    }
+
+    pub fn map(&self, timestamp: u64, duration: u64) -> Presentation {
+        let mut media_edits = self.edits.iter().filter_map(|edit| Some((edit, edit.media_time?))).peekable();
+        // Frames outside the edits keep their distance to the first one, so their timestamps
+        // never collide with presented frames
+        let first = media_edits.peek().copied();
+
+        for (edit, media_time) in media_edits {
+            if edit.rate == 0.0 {
+                if timestamp <= media_time && media_time < timestamp + duration.max(1) {
+                    return Presentation { timestamp: edit.start as i64, duration: edit.duration.unwrap_or(duration), decode_only: false };
+                }
+                continue;
+            }
+
+            let media_end = edit.duration.map(|duration| media_time + (duration as f64 * edit.rate) as u64);
+            if timestamp >= media_time && media_end.is_none_or(|end| timestamp < end) {
+                return Presentation {
+                    timestamp: (edit.start as f64 + (timestamp - media_time) as f64 / edit.rate).round() as i64,
+                    duration: (duration as f64 / edit.rate).round() as u64,
+                    decode_only: false,
+                };
+            }
+        }
+
+        match first {
+            Some((edit, media_time)) => Presentation {
+                timestamp: edit.start as i64 + timestamp as i64 - media_time as i64,
+                duration,
+                decode_only: true,
+            },
+            None => Presentation { timestamp: timestamp as i64, duration, decode_only: !self.edits.is_empty() },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Edit, Presentation, Timeline};
+
+    #[test]
+    fn it_maps_frames_through_edits() {
+        // An empty edit of 100ms, then the media from 40ms on for 1s
+        let timeline = Timeline::new(vec![
+            Edit { start: 0, duration: Some(100_000), media_time: None, rate: 1.0 },
+            Edit { start: 100_000, duration: Some(1_000_000), media_time: Some(40_000), rate: 1.0 },
+        ]);
+
+        assert_eq!(timeline.map(40_000, 20_000), Presentation { timestamp: 100_000, duration: 20_000, decode_only: false });
+        assert_eq!(timeline.map(0, 20_000), Presentation { timestamp: 60_000, duration: 20_000, decode_only: true });
+        assert!(timeline.map(1_040_000, 20_000).decode_only);
+        assert_eq!(timeline.duration(), Some(1_100_000));
+
+        let codec_delay = Timeline::shift(20_000);
+        assert_eq!(codec_delay.map(0, 20_000).timestamp, -20_000);
+        assert!(codec_delay.map(0, 20_000).decode_only);
+        assert_eq!(codec_delay.map(60_000, 20_000), Presentation { timestamp: 40_000, duration: 20_000, decode_only: false });
+
+        let slow_motion = Timeline::new(vec![Edit { start: 0, duration: Some(1_000_000), media_time: Some(0), rate: 0.5 }]);
+        assert_eq!(slow_motion.map(200_000, 20_000), Presentation { timestamp: 400_000, duration: 40_000, decode_only: false });
+        assert!(slow_motion.map(500_000, 20_000).decode_only);
+
+        assert_eq!(Timeline::default().map(10, 20), Presentation { timestamp: 10, duration: 20, decode_only: false });
+        assert_eq!(Timeline::default().duration(), None);
+        assert_eq!(codec_delay.duration(), None);
+    }
+}
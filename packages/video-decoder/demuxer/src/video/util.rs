@@ -19,8 +19,47 @@ impl<'a> Bits<'a> {
         Ok(self.bits.read_bits(size as u32).ok_or(format!("Could not read {description}"))? as u8)
     }
 
+    pub fn read_u32(&mut self, size: u8, description: &str) -> Result<u32, String> {
+        Ok(self.bits.read_bits(size as u32).ok_or(format!("Could not read {description}"))? as u32)
+    }
+
+    /// An unsigned exponential-Golomb code, like the uvlc() of AV1. 32 or more leading zeros are
+    /// the largest value.
+    pub fn read_uvlc(&mut self, description: &str) -> Result<u32, String> {
+        let mut leading_zeros = 0;
+        while !self.read_bool(description)? {
+            leading_zeros += 1;
+            if leading_zeros == 32 {
+                return Ok(u32::MAX);
+            }
+        }
+
+        // Reading no bits at all is a panic
+        if leading_zeros == 0 {
+            return Ok(0);
+        }
+
+        Ok(self.read_u32(leading_zeros, description)? + ((1u64 << leading_zeros) - 1) as u32)
+    }
+
     pub fn skip(&mut self, size: u8, description: &str) -> Result<(), String> {
         self.bits.read_bits(size as u32).ok_or(format!("Could not read {description}"))?;
         Ok(())
     }
- }
\ No newline at end of file
+ }
+
+#[cfg(test)]
+mod tests {
+    use super::Bits;
+
+    #[test]
+    fn it_reads_uvlc_codes() {
+        // 0, 1 & 4
+        let mut bits = Bits::new(&[0b1010_0010, 0b1000_0000]);
+        assert_eq!(bits.read_uvlc("value"), Ok(0));
+        assert_eq!(bits.read_uvlc("value"), Ok(1));
+        assert_eq!(bits.read_uvlc("value"), Ok(4));
+
+        assert_eq!(Bits::new(&[0; 40]).read_uvlc("value"), Ok(u32::MAX));
+    }
+}
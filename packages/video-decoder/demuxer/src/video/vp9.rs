@@ -0,0 +1,174 @@
+use super::{util::Bits, vpcc::Vpcc, color::ColorSpace};
+
+const FRAME_MARKER: u8 = 2;
+const SYNC_CODE: [u8; 3] = [0x49, 0x83, 0x42];
+const KEY_FRAME: bool = false;
+const CS_RGB: u8 = 7;
+
+// The matrix coefficients of the VP9 color_space values
+const MATRIX_COEFFICIENTS: [u8; 8] = [2, 5, 1, 6, 7, 9, 2, 0];
+
+/// The parts of the uncompressed header of a VP9 keyframe that describe the stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub profile: u8,
+    pub bit_depth: u8,
+    pub color_space: u8,
+    pub full_range: bool,
+    pub subsampling_x: bool,
+    pub subsampling_y: bool,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FrameHeader {
+    // https://storage.googleapis.com/downloads.webmproject.org/docs/vp9/vp9-bitstream-specification-v0.6-20160331-draft.pdf
+    // 6.2 Uncompressed header syntax, `None` when the frame isn't a keyframe. The first frame of a
+    // superframe starts at the start of it, so those can be read as well.
+    pub fn from(frame: &[u8]) -> Result<Option<Self>, String> {
+        let mut bits = Bits::new(frame);
+        if bits.read_u8(2, "frame_marker")? != FRAME_MARKER {
+            return Err("Invalid VP9 frame marker".to_string());
+        }
+
+        let profile_low_bit = bits.read_u8(1, "profile_low_bit")?;
+        let profile = (bits.read_u8(1, "profile_high_bit")? << 1) | profile_low_bit;
+        if profile == 3 {
+            bits.skip(1, "reserved_zero")?;
+        }
+
+        if bits.read_bool("show_existing_frame")? || bits.read_bool("frame_type")? != KEY_FRAME {
+            return Ok(None);
+        }
+        bits.skip(2, "show_frame & error_resilient_mode")?;
+
+        for byte in SYNC_CODE {
+            if bits.read_u8(8, "frame_sync_code")? != byte {
+                return Err("Invalid VP9 sync code".to_string());
+            }
+        }
+
+        let bit_depth = match profile >= 2 && bits.read_bool("ten_or_twelve_bit")? {
+            true => 12,
+            false if profile >= 2 => 10,
+            false => 8,
+        };
+        let color_space = bits.read_u8(3, "color_space")?;
+        let has_chroma_subsampling = profile == 1 || profile == 3;
+
+        let (full_range, subsampling_x, subsampling_y) = match color_space {
+            CS_RGB => {
+                if has_chroma_subsampling {
+                    bits.skip(1, "reserved_zero")?;
+                }
+                (true, false, false)
+            },
+            _ => {
+                let full_range = bits.read_bool("color_range")?;
+                match has_chroma_subsampling {
+                    true => {
+                        let subsampling = (full_range, bits.read_bool("subsampling_x")?, bits.read_bool("subsampling_y")?);
+                        bits.skip(1, "reserved_zero")?;
+                        subsampling
+                    },
+                    false => (full_range, true, true),
+                }
+            },
+        };
+
+        let width = bits.read_u32(16, "frame_width_minus_1")? + 1;
+        let height = bits.read_u32(16, "frame_height_minus_1")? + 1;
+
+        Ok(Some(Self { profile, bit_depth, color_space, full_range, subsampling_x, subsampling_y, width, height }))
+    }
+
+    /// The bitstream only signals the matrix & range
+    pub fn color_space(self) -> ColorSpace {
+        ColorSpace::new(2, 2, MATRIX_COEFFICIENTS[self.color_space as usize & 0x07], self.full_range)
+    }
+
+    /// Like the vpcC box FFmpeg writes for the same bitstream: 4:2:0 has no chroma siting and only
+    /// the matrix is known
+    pub fn to_vpcc(self, level: u8) -> Vpcc {
+        let chroma_subsampling = match (self.subsampling_x, self.subsampling_y) {
+            (true, true) => 0,
+            (true, false) => 2,
+            _ => 3,
+        };
+        let matrix = MATRIX_COEFFICIENTS[self.color_space as usize & 0x07];
+
+        Vpcc::new(self.profile, level, self.bit_depth, chroma_subsampling).with_color_space(ColorSpace {
+            primaries: Some(2),
+            transfer: Some(2),
+            matrix: Some(matrix),
+            full_range: Some(self.full_range),
+        })
+    }
+}
+
+// https://www.webmproject.org/vp9/levels/: level, max luma samples per second, max luma picture
+// size & max bitrate in kbit/s
+const LEVELS: [(u8, u64, u64, f64); 14] = [
+    (10, 829_440, 36_864, 200.0),
+    (11, 2_764_800, 73_728, 800.0),
+    (20, 4_608_000, 122_880, 1_800.0),
+    (21, 9_216_000, 245_760, 3_600.0),
+    (30, 20_736_000, 552_960, 7_200.0),
+    (31, 36_864_000, 983_040, 12_000.0),
+    (40, 83_558_400, 2_228_224, 18_000.0),
+    (41, 160_432_128, 2_228_224, 30_000.0),
+    (50, 311_951_360, 8_912_896, 60_000.0),
+    (51, 588_251_136, 8_912_896, 120_000.0),
+    (52, 1_176_502_272, 8_912_896, 180_000.0),
+    (60, 1_176_502_272, 35_651_584, 180_000.0),
+    (61, 2_353_004_544, 35_651_584, 240_000.0),
+    (62, 4_706_009_088, 35_651_584, 480_000.0),
+];
+
+/// The lowest level that allows the picture size, frame rate & bitrate in kbit/s, the frame rate
+/// and bitrate are left out when they aren't known
+pub fn estimate_level(width: u32, height: u32, frame_rate: Option<f64>, bitrate: Option<f64>) -> u8 {
+    let picture_size = width as u64 * height as u64;
+    let sample_rate = frame_rate.map(|frame_rate| (picture_size as f64 * frame_rate) as u64);
+
+    LEVELS
+        .iter()
+        .find(|(_, max_sample_rate, max_picture_size, max_bitrate)| {
+            picture_size <= *max_picture_size
+                && sample_rate.is_none_or(|sample_rate| sample_rate <= *max_sample_rate)
+                && bitrate.is_none_or(|bitrate| bitrate <= *max_bitrate)
+        })
+        .map_or(62, |(level, ..)| *level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_level, FrameHeader};
+    use crate::video::CodecPrivate;
+
+    #[test]
+    fn it_reads_the_header_of_a_keyframe() {
+        // Profile 0 keyframe, BT.709, 640x480
+        let keyframe = [0x82, 0x49, 0x83, 0x42, 0x40, 0x27, 0xF0, 0x1D, 0xF0];
+        let header = FrameHeader::from(&keyframe).unwrap().unwrap();
+        assert_eq!(header, FrameHeader {
+            profile: 0,
+            bit_depth: 8,
+            color_space: 2,
+            full_range: false,
+            subsampling_x: true,
+            subsampling_y: true,
+            width: 640,
+            height: 480,
+        });
+        assert_eq!(header.to_vpcc(30).to_codec_string(), "vp09.00.30.08.00.02.02.01.00");
+
+        // An inter frame
+        assert_eq!(FrameHeader::from(&[0x86, 0x00]), Ok(None));
+
+        assert_eq!(estimate_level(640, 480, Some(30.0), None), 30);
+        assert_eq!(estimate_level(1280, 720, Some(30.0), Some(1000.0)), 31);
+        assert_eq!(estimate_level(1280, 720, Some(30.0), Some(15_000.0)), 40);
+        assert_eq!(estimate_level(1920, 1080, None, None), 40);
+    }
+}
@@ -1,22 +1,112 @@
-use super::CodecPrivate;
+use super::{CodecPrivate, color::ColorSpace};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Vpcc {
     pub profile: u8,
     pub level: u8,
     pub bit_depth: u8,
     pub chroma_subsampling: u8,
+    pub color_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    pub video_full_range_flag: bool,
+}
+
+// The values the short form of the codec string implies, BT.709 in limited range with 4:2:0
+// chroma subsampling colocated with luma
+const DEFAULT_CHROMA_SUBSAMPLING: u8 = 1;
+const DEFAULT_COLOR: u8 = 1;
+
+impl Vpcc {
+    /// A configuration with the default colours, like a Matroska CodecPrivate describes it
+    pub fn new(profile: u8, level: u8, bit_depth: u8, chroma_subsampling: u8) -> Self {
+        Self {
+            profile,
+            level,
+            bit_depth,
+            chroma_subsampling,
+            color_primaries: DEFAULT_COLOR,
+            transfer_characteristics: DEFAULT_COLOR,
+            matrix_coefficients: DEFAULT_COLOR,
+            video_full_range_flag: false,
+        }
+    }
+
+    // https://www.webmproject.org/vp9/mp4/#vp-codec-configuration-box, from the body of a vpcC
+    // box version 1
+    pub fn from(vpcc: &[u8]) -> Result<Self, String> {
+        let fields = vpcc.get(4..10).ok_or("vpcC is too short")?;
+
+        Ok(Self {
+            profile: fields[0],
+            level: fields[1],
+            bit_depth: fields[2] >> 4,
+            chroma_subsampling: (fields[2] >> 1) & 0x07,
+            video_full_range_flag: fields[2] & 0x01 != 0,
+            color_primaries: fields[3],
+            transfer_characteristics: fields[4],
+            matrix_coefficients: fields[5],
+        })
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        ColorSpace::new(self.color_primaries, self.transfer_characteristics, self.matrix_coefficients, self.video_full_range_flag)
+    }
+
+    /// Takes the colours `color_space` describes, like those of the Matroska Colour element
+    pub fn with_color_space(self, color_space: ColorSpace) -> Self {
+        Self {
+            color_primaries: color_space.primaries.unwrap_or(self.color_primaries),
+            transfer_characteristics: color_space.transfer.unwrap_or(self.transfer_characteristics),
+            matrix_coefficients: color_space.matrix.unwrap_or(self.matrix_coefficients),
+            video_full_range_flag: color_space.full_range.unwrap_or(self.video_full_range_flag),
+            ..self
+        }
+    }
+
+    fn has_default_color(&self) -> bool {
+        self.chroma_subsampling == DEFAULT_CHROMA_SUBSAMPLING
+            && self.color_primaries == DEFAULT_COLOR
+            && self.transfer_characteristics == DEFAULT_COLOR
+            && self.matrix_coefficients == DEFAULT_COLOR
+            && !self.video_full_range_flag
+    }
 }
 
 impl CodecPrivate for Vpcc {
+    // https://www.webmproject.org/vp9/mp4/#codecs-parameter-string, the optional fields are all
+    // left out or all included
     fn to_codec_string(&self) -> String {
+        let short = format!("vp09.{:02}.{:02}.{:02}", self.profile, self.level, self.bit_depth);
+        if self.has_default_color() {
+            return short;
+        }
+
         // cccc.PP.LL.DD.CC.cp.tc.mc.FF
         format!(
-            "vp09.{:02}.{:02}.{:02}.{:02}", 
-            self.profile, 
-            self.level, 
-            self.bit_depth, 
+            "{short}.{:02}.{:02}.{:02}.{:02}.{:02}",
             self.chroma_subsampling,
+            self.color_primaries,
+            self.transfer_characteristics,
+            self.matrix_coefficients,
+            self.video_full_range_flag as u8,
         )
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::video::{CodecPrivate, color::ColorSpace, vpcc::Vpcc};
+
+    #[test]
+    fn it_only_includes_the_colours_when_they_differ_from_the_defaults() {
+        assert_eq!(Vpcc::new(0, 10, 8, 1).to_codec_string(), "vp09.00.10.08");
+
+        let hdr10 = Vpcc::new(2, 41, 10, 1).with_color_space(ColorSpace::new(9, 16, 9, false));
+        assert_eq!(hdr10.to_codec_string(), "vp09.02.41.10.01.09.16.09.00");
+
+        // The vpcC box of video_2s_30fps_1280x720_vp9.mp4
+        let vpcc = Vpcc::from(&[0x01, 0x00, 0x00, 0x00, 0x01, 0x1F, 0x87, 0x02, 0x02, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(vpcc.to_codec_string(), "vp09.01.31.08.03.02.02.00.01");
+    }
+}
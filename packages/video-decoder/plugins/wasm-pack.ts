@@ -9,7 +9,7 @@ interface Options {
 }
 
 function watch(crate: string, outDir: string, outName: string): ChildProcess {
-    const args = ['watch', '-i', '.gitignore', '-i', 'pkg/*', '-s', `wasm-pack build --release --out-name "${outName}" --out-dir "${outDir}"`];
+    const args = ['watch', '-i', '.gitignore', '-i', 'pkg/*', '-s', `wasm-pack build --release --out-name "${outName}" --out-dir "${outDir}" -- --features web`];
     return spawn('cargo', args, {
         cwd: resolve(crate),
         stdio: 'inherit',
@@ -17,7 +17,7 @@ function watch(crate: string, outDir: string, outName: string): ChildProcess {
 }
 
 function build(crate: string, outDir: string, outName: string) {
-    execSync(`wasm-pack build --release --out-name "${outName}" --out-dir "${outDir}"`, {
+    execSync(`wasm-pack build --release --out-name "${outName}" --out-dir "${outDir}" -- --features web`, {
         cwd: resolve(crate),
         stdio: 'inherit',
     });
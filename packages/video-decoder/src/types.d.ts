@@ -6,9 +6,99 @@ declare module '@crate/demuxer' {
     */
     export function load(buffer: ArrayBuffer, format: string): Demuxer;
 
+   /**
+    * @param {ArrayBuffer} buffer
+    * @returns {string}
+    */
+    export function detectFormat(buffer: ArrayBuffer): 'mkv' | 'mp4' | 'ivf' | 'ts' | 'raw';
+
+   /**
+    * @param {ArrayBuffer} buffer
+    * @returns {Demuxer}
+    */
+    export function loadAuto(buffer: ArrayBuffer): Demuxer;
+
+    export interface RangeReader {
+        size: number;
+        read(offset: number, length: number): Promise<Uint8Array>;
+    }
+
+   /**
+    * @param {RangeReader} reader
+    * @param {string | undefined} format
+    * @returns {Promise<Demuxer>}
+    */
+    export function loadRange(reader: RangeReader, format?: 'mkv' | 'mp4'): Promise<Demuxer>;
+
+    export interface FrameInfo {
+        index: number;
+        decodeIndex: number;
+        /** In microseconds, like all frame times */
+        decodeTimestamp: number;
+        timestamp: number;
+        duration?: number;
+        size: number;
+        keyframe: boolean;
+        offset: number;
+    }
+
+    export interface FrameRate {
+        /** The declared rate when the frames match it, the measured rate otherwise */
+        rate: number;
+        declared?: number;
+        /** Whether the intervals between the frames differ */
+        variable: boolean;
+        /** In microseconds */
+        minInterval: number;
+        maxInterval: number;
+        meanInterval: number;
+    }
+
+    export interface MasteringDisplay {
+        /** CIE 1931 x & y */
+        red: [number, number];
+        green: [number, number];
+        blue: [number, number];
+        whitePoint: [number, number];
+        /** In cd/m² */
+        maxLuminance: number;
+        minLuminance: number;
+    }
+
+    export interface ColorInfo {
+        colorSpace: VideoColorSpaceInit;
+        masteringDisplay?: MasteringDisplay;
+        /** In cd/m² */
+        contentLightLevel?: { maxCLL: number, maxFALL: number };
+    }
+
     export class Demuxer {
         free(): void;
 
+        /**
+        * @param {string | undefined} format
+        */
+        constructor(format?: 'mkv' | 'mp4');
+
+        /**
+        * @param {Uint8Array} bytes
+        */
+        append(bytes: Uint8Array): void;
+
+        endOfStream(): void;
+
+        /**
+        * @returns {boolean}
+        */
+        ready(): boolean;
+
+        /**
+        * @param {number} from
+        * @param {number} to
+        * @returns {Promise<void>}
+        */
+        fetch(from: number, to: number): Promise<void>;
+
         /**
         * @returns {number}
         */
@@ -25,6 +115,26 @@ declare module '@crate/demuxer' {
         codec(): string | undefined;
 
         /**
+        * @returns {Uint8Array | undefined}
+        */
+        description(): Uint8Array | undefined;
+
+        /**
+        * The colour space to configure the decoder with and the HDR metadata to display the frames
+        * @returns {ColorInfo | undefined}
+        */
+        colorInfo(): ColorInfo | undefined;
+
+        /**
+        * @param {string | undefined} codec
+        * @param {HardwareAcceleration | undefined} hardwareAcceleration
+        * @param {boolean | undefined} optimizeForLatency
+        * @returns {VideoDecoderConfig}
+        */
+        decoderConfig(codec?: string, hardwareAcceleration?: HardwareAcceleration, optimizeForLatency?: boolean): VideoDecoderConfig;
+
+        /**
+        * The size to display the frames at before they are rotated
         * @returns {number | undefined}
         */
         displayWidth(): number | undefined;
@@ -35,6 +145,25 @@ declare module '@crate/demuxer' {
         displayHeight(): number | undefined;
 
         /**
+        * The width & height of a pixel, undefined for square pixels
+        * @returns {Uint32Array | undefined}
+        */
+        pixelAspectRatio(): Uint32Array | undefined;
+
+        /**
+        * The clockwise rotation in degrees to display the frames upright, after flipping them
+        * @returns {number}
+        */
+        rotation(): 0 | 90 | 180 | 270;
+
+        /**
+        * Whether the frames have to be mirrored horizontally before rotating them
+        * @returns {boolean}
+        */
+        flip(): boolean;
+
+        /**
+        * The duration in seconds
         * @returns {number}
         */
         duration(): number;
@@ -45,12 +174,37 @@ declare module '@crate/demuxer' {
         frameCount(): number;
 
         /**
-        * @param {number} timestamp
+        * @param {number} timestamp The timestamp in microseconds
         * @returns {number | undefined}
         */
         timestampToFrame(timestamp: number): number | undefined;
 
         /**
+        * The loaded frames in presentation order
+        * @returns {FrameInfo[]}
+        */
+        frames(): FrameInfo[];
+
+        /**
+        * The duration of a frame in microseconds
+        * @param {number} idx
+        * @returns {number | undefined}
+        */
+        frameDuration(idx: number): number | undefined;
+
+        /**
+        * The frame rate of the loaded frames, checked against the rate the container declares
+        * @returns {FrameRate | undefined}
+        */
+        frameRate(): FrameRate | undefined;
+
+        /**
+        * @param {number} size
+        */
+        setChunkCacheSize(size: number): void;
+
+        /**
+        * Frame indices are in presentation order
         * @param {number} from
         * @param {number} to
         * @param {VideoDecoder} decoder
@@ -64,6 +218,84 @@ declare module '@crate/demuxer' {
         * @returns {number}
         */
         seek(frame: number, decoder: VideoDecoder): number;
+
+        /**
+        * Decodes up to the frame picked for the time in seconds, returns the time of that frame
+        * @param {number} seconds
+        * @param {'nearest' | 'previousKeyframe' | 'nextKeyframe' | 'nearestKeyframe' | undefined} mode
+        * @param {VideoDecoder} decoder
+        * @returns {number | undefined}
+        */
+        seekToTime(seconds: number, mode: 'nearest' | 'previousKeyframe' | 'nextKeyframe' | 'nearestKeyframe' | undefined, decoder: VideoDecoder): number | undefined;
+
+        /**
+        * Decodes the frames shown from `start` until `end` in seconds
+        * @param {number} start
+        * @param {number} end
+        * @param {VideoDecoder} decoder
+        * @returns {number}
+        */
+        decodeTimeRange(start: number, end: number, decoder: VideoDecoder): number;
+
+        /**
+        * Decodes only the keyframes nearest to every `stride`th frame, for thumbnails with a
+        * separate decoder. Returns the indices of the decoded keyframes.
+        * @param {number} from
+        * @param {number} to
+        * @param {number} stride
+        * @param {VideoDecoder} decoder
+        * @returns {Uint32Array}
+        */
+        decodeKeyframes(from: number, to: number, stride: number, decoder: VideoDecoder): Uint32Array;
+
+        /**
+        * The group of pictures (the frames from one keyframe up to the next) the frame is decoded in
+        * @param {number} frame
+        * @returns {number | undefined}
+        */
+        gopOf(frame: number): number | undefined;
+
+        /**
+        * The first and last frame of a group of pictures
+        * @param {number} gop
+        * @returns {Uint32Array | undefined}
+        */
+        gopRange(gop: number): Uint32Array | undefined;
+
+        /**
+        * @returns {number}
+        */
+        gopCount(): number;
+
+        /**
+        * Decodes a whole group of pictures and returns the frames it outputs, for reverse playback
+        * @param {number} gop
+        * @param {VideoDecoder} decoder
+        * @returns {Uint32Array}
+        */
+        decodeGop(gop: number, decoder: VideoDecoder): Uint32Array;
+
+        /**
+        * The frames (in decode order) to feed a decoder so it outputs the frames `from` up to and
+        * including `to`, given the last frame it got since it was configured, reset or flushed
+        * @param {number} from
+        * @param {number} to
+        * @param {number | undefined} lastDecoded
+        * @returns {Uint32Array}
+        */
+        planDecode(from: number, to: number, lastDecoded?: number): Uint32Array;
+
+        /**
+        * The chunk of a frame, in decode order like the frames of `planDecode`
+        * @param {number} idx
+        * @returns {EncodedVideoChunk}
+        */
+        chunk(idx: number): EncodedVideoChunk;
+
+        /**
+        * Has to be called after the decoder passed to `decode` and `seek` is reset, flushed or failed
+        */
+        resetDecoderState(): void;
     }
 }
 
@@ -1,4 +1,4 @@
-import { Demuxer, load } from '@crate/demuxer';
+import { Demuxer, loadAuto } from '@crate/demuxer';
 import Pages from './Pages';
 import WebGLRenderer from './WebGLRenderer';
 import EventEmitter from '../util/EventEmitter';
@@ -217,15 +217,10 @@ class WebWorkerDecoder {
         const buffer = await response.arrayBuffer();
         logger.timeEnd('load video file');
         logger.time('demux');
-        const demuxer = load(buffer, WebWorkerDecoder.getContentType(response));
+        const demuxer = loadAuto(buffer);
         logger.timeEnd('demux');
-        const config: MyVideoDecoderConfig = {
-            codec: demuxer.codec() || options.codec,
-            codedWidth: demuxer.codedWidth(),
-            codedHeight: demuxer.codedHeight(),
-            colorSpace: {}
-        }
-        
+        const config = demuxer.decoderConfig(options.codec) as MyVideoDecoderConfig;
+
         const supported = await VideoDecoder.isConfigSupported(config);
 
         if (supported.supported !== true) {
@@ -234,26 +229,6 @@ class WebWorkerDecoder {
 
         return new WebWorkerDecoder(logger, demuxer, config, options);
     }
-
-    private static getContentType(response: Response): string {
-        const type = response.headers.get('Content-Type');
-        const fallback = 'mp4';
-
-        if (typeof type === 'string') {
-            const pattern = /^video\/(?<format>.*)$/;
-            const match = type.match(pattern);
-            if (match && match.groups) {
-                switch (match.groups['format']) {
-                    case 'mkv':
-                    case 'webm': return 'mkv';
-                    case 'mp4': return 'mp4';
-                    default: return fallback;
-                }
-            }
-        }
-
-        return fallback;
-    }
 }
 
 export default WebWorkerDecoder;
\ No newline at end of file